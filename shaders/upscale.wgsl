@@ -16,10 +16,25 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     return result;
 }
 
+struct Grid {
+    // Alpha is the opacity of the lines, 0.0 - no grid
+    color: vec4<f32>,
+};
+
 @group(0) @binding(0) var r_color: texture_2d<f32>;
 @group(0) @binding(1) var r_sampler: sampler;
+@group(0) @binding(2) var<uniform> r_grid: Grid;
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(r_color, r_sampler, vertex.tex_coord);
+    let color = textureSample(r_color, r_sampler, vertex.tex_coord);
+
+    // Position inside the CHIP-8 pixel, the line is one window pixel thick
+    // at the top and left edges of each CHIP-8 pixel
+    let texel = vertex.tex_coord * vec2<f32>(textureDimensions(r_color));
+    let line_width = fwidth(texel);
+    let on_line = any(fract(texel) < line_width);
+
+    let alpha = select(0.0, r_grid.color.a, on_line);
+    return vec4<f32>(mix(color.rgb, r_grid.color.rgb, alpha), color.a);
 }
@@ -1,23 +1,40 @@
+mod clipboard;
 mod context;
+mod cpu_thread;
+mod raw_output;
+mod renderer;
+mod software;
 mod ui;
 
-use std::{sync::Arc, time::{Duration, Instant}};
+use std::{fs, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant, SystemTime}};
 
 use context::Context;
+use cpu_thread::CpuThread;
+pub use context::ContextError;
+pub use raw_output::RawOutput;
+use renderer::Renderer;
+use software::SoftwareRenderer;
 use ui::Ui;
 use winit::{
     application::ApplicationHandler,
-    dpi::LogicalSize,
-    event::{ ElementState, KeyEvent, StartCause, WindowEvent },
+    dpi::{ LogicalSize, PhysicalPosition, PhysicalSize },
+    event::{ ElementState, KeyEvent, MouseButton, StartCause, WindowEvent },
     event_loop::{ ActiveEventLoop, ControlFlow },
     keyboard::{ KeyCode, PhysicalKey },
-    window::{ Window, WindowId },
+    window::{ Fullscreen, Icon, Window, WindowId },
 };
 
 use crate::{
     buzzer::Buzzer,
-    config::{ Config, DrawStrategy, DEFAULT_SPEED, MAX_SPEED },
-    cpu::{ Cpu, DISPLAY_DATA_LEN, DISPLAY_HEIGHT, DISPLAY_WIDTH },
+    config::{ Color, Config, DrawStrategy, FastForwardAudio, Palette, SavedState, WindowPos, DEFAULT_SPEED, MAX_SPEED },
+    cpu::{ Cpu, Rect },
+    headless,
+    png,
+    keymap::Action,
+    replay::{ Event, InputLog },
+    rng::{self, Rng},
+    rom,
+    timing,
 };
 
 // Consts
@@ -25,6 +42,36 @@ use crate::{
 pub const DISPLAY_SCALE: u32 = 8;
 /// Delay in milliseconds between frames (1000 / FPS)
 pub const TARGET_DELAY: u64 = 16;
+/// How many frames are emulated in one frame while fast forwarding
+pub const FASTFORWARD_FRAMES: u16 = 2;
+/// How long the CPU may be stepped in a frame, the rest of the frame is left for the window
+const THROTTLE_BUDGET: Duration = Duration::from_millis(TARGET_DELAY * 3 / 4);
+/// Cycles between the checks of the time spent in a frame, the clock isn't free either
+const THROTTLE_CHECK_CYCLES: u32 = 1024;
+/// Delay in milliseconds between game file modification checks (see [Config::watch])
+const WATCH_DELAY: u64 = 500;
+/// Frames without the user input before the attract mode starts playing (see [Config::attract])
+const ATTRACT_IDLE_FRAMES: u32 = 60 * 5;
+/// Frames between the button presses in the attract mode
+const ATTRACT_PRESS_FRAMES: u32 = 10;
+/// How bright the just turned off pixels are, from 0.0 (off) to 1.0 (on) (see [Config::afterglow])
+const AFTERGLOW_INTENSITY: f32 = 0.4;
+/// How much the intensity of a turned off pixel decreases every frame (see [DrawStrategy::Fade])
+const FADE_STEP: u8 = 48;
+/// Outline of the last drawn sprite, a color that palettes rarely have (see [Config::highlight_draws])
+const HIGHLIGHT_COLOR: Color = Color(255, 0, 255);
+
+/// Window icon: the letter "p" from the UI font, raw 32x32 RGBA pixels
+const ICON: &[u8] = include_bytes!("../assets/icon.rgba");
+const ICON_SIZE: u32 = 32;
+
+/// Some platforms (for example, Wayland) don't support window icons at all,
+/// they are simply ignored there
+fn window_icon() -> Option<Icon> {
+    Icon::from_rgba(ICON.to_vec(), ICON_SIZE, ICON_SIZE)
+        .map_err(|e| eprintln!("Unable to load the window icon: {}", e))
+        .ok()
+}
 
 /// Convert [KeyCode] to CHIP-8 button
 fn key_to_btn(keycode: KeyCode) -> Option<u8> {
@@ -52,100 +99,886 @@ fn key_to_btn(keycode: KeyCode) -> Option<u8> {
     }
 }
 
+/// Layout of the CHIP-8 keypad, used to map mouse clicks to buttons
+const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// Returns the modification time of the file, if it is available
+fn file_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Print how many times each ignored instruction was encountered, for example "0x5123 ×4"
+fn print_ignored(cpu: &Cpu) {
+    let ignored = cpu.ignored_instructions();
+    if ignored.is_empty() {
+        crate::message!("No instructions were ignored");
+        return;
+    }
+
+    crate::message!("Ignored instructions:");
+    for (ins, count) in ignored {
+        crate::message!("    {:#06X} ×{}", ins, count);
+    }
+}
+
+/// Which CPUs get the pressed buttons in the comparison mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputTarget {
+    Both,
+    Left,
+    Right,
+}
+impl InputTarget {
+    fn next(self) -> Self {
+        match self {
+            Self::Both => Self::Left,
+            Self::Left => Self::Right,
+            Self::Right => Self::Both,
+        }
+    }
+}
+
+/// Cross-fade of the game colors into the new palette (see [Config::palette_fade])
+struct PaletteFade {
+    /// Colors shown right before the palette has changed
+    from: Palette,
+    /// Frames passed since the palette has changed
+    frame: u8,
+}
+
+/// Display shown on the screen, it is copied from the CPU only when the draw strategy presents
+/// the display, so the screen never shows what the game has drawn halfway into a frame:
+/// - [DrawStrategy::Frame] and [DrawStrategy::Fade] present it at the end of every frame
+/// - [DrawStrategy::Step] presents it after every draw and clear too, the intermediate states are the point of it
+#[derive(Default)]
+struct FrameBuffer {
+    /// Planes of every pixel (see [Cpu::pixel_planes])
+    planes: Vec<u8>,
+    /// Region changed since the last render
+    dirty: Option<Rect>,
+    /// Last drawn sprite since the last render (see [Config::highlight_draws])
+    last_draw: Option<Rect>,
+    /// Whether the display was cleared and nothing was drawn after that (see [Config::defer_clear])
+    clear_pending: bool,
+}
+impl FrameBuffer {
+    /// Copy the display of the `cpu` and take its changes
+    fn present(&mut self, cpu: &mut Cpu) {
+        self.planes.clear();
+        self.planes.extend((0..cpu.display.len()).map(|i| cpu.pixel_planes(i)));
+        self.dirty = Rect::merge(self.dirty, cpu.take_dirty());
+        self.last_draw = cpu.take_last_draw().or(self.last_draw);
+        self.clear_pending = cpu.is_clear_pending();
+    }
+    fn is_on(&self, index: usize) -> bool {
+        self.planes[index] != 0
+    }
+}
+
+/// Second game running on the right side of the window, to compare it with the main one
+/// Useful to see how the quirks change the behaviour of the game
+pub struct Compare {
+    pub cpu: Cpu,
+    /// Whether it runs the same game as the main CPU, then it is reloaded together with it
+    /// (see [Config::watch])
+    pub same_game: bool,
+}
+
 /// App
 pub struct App<'win> {
     win: Option<Arc<Window>>,
-    context: Option<Context<'win>>,
+    context: Option<Box<dyn Renderer + 'win>>,
 
     config: Config,
     cpu: Cpu,
+    /// CPU shown next to the main one, if comparing
+    compare: Option<Compare>,
+    /// Which CPUs are controlled by the user in the comparison mode
+    input_target: InputTarget,
     buzzer: Option<Buzzer>,
     ui: Ui,
     /// This screen data is NOT controlled by a CHIP-8 program,
     /// but only used to add my own UI "above" the CHIP-8 display
-    screen: [Option<bool>; DISPLAY_DATA_LEN],
+    screen: Vec<Option<bool>>,
+    /// Region of the UI screen drawn in this frame
+    ui_bounds: Option<Rect>,
+    /// Region of the UI screen changed since the last render
+    ui_dirty: Option<Rect>,
+    /// Intensity of each pixel from 0 (off) to 255 (on), used by [DrawStrategy::Fade]
+    fade: Vec<u8>,
+    /// CHIP-8 display at the previous render, used by [Config::afterglow]
+    prev_display: Vec<bool>,
+    /// Presented displays of the main and the compared CPUs, the screen is rendered from them
+    back: FrameBuffer,
+    compare_back: FrameBuffer,
+    /// Region of the glowing pixels, they should be turned off on the next render
+    glow_dirty: Option<Rect>,
+    /// Outlined sprite, it is erased on the next render (see [Config::highlight_draws])
+    highlight: Option<Rect>,
+    /// Whether the whole screen should be rerendered (for example, palette was changed)
+    full_redraw: bool,
+    /// Whether the window title should be updated
+    title_dirty: bool,
+    /// Whether rendering of the pending clear was already delayed for a frame
+    /// (see [Config::defer_clear])
+    clear_deferred: bool,
 
     is_paused: bool,
     is_fastforward: bool,
+    /// Whether the window is out of focus
+    is_unfocused: bool,
+    /// Whether the game was paused because the window lost focus (see [Config::pause_on_unfocus])
+    paused_by_unfocus: bool,
+    /// Whether the game was paused because the user hasn't touched anything (see [Config::auto_pause])
+    paused_by_idle: bool,
+    /// When the last keyboard or mouse input happened
+    last_input: Instant,
+    /// Timer ticks made by hand during the pause (see [App::step_timers_paused])
+    paused_ticks: u64,
+    /// Frames the game has run without drawing or clearing anything since it was loaded,
+    /// `None` once it has drawn or the user was warned (see [Config::blank_warning])
+    blank_frames: Option<u32>,
 
     last_time: Instant,
+
+    /// Path to the currently running game
+    game_path: PathBuf,
+    /// File name of the currently running game
+    game_name: String,
+    /// Size of the currently running game in bytes
+    game_size: usize,
+    /// Last known modification time of the game file
+    game_modified: Option<SystemTime>,
+    last_watch_time: Instant,
+
+    /// Last known cursor position inside the window
+    cursor_pos: PhysicalPosition<f64>,
+    /// Whether the cursor is shown over the window (see [Config::show_cursor])
+    cursor_visible: bool,
+    /// CHIP-8 button currently held with the mouse (see [Config::mouse_keypad])
+    mouse_btn: Option<u8>,
+
+    /// Whether the auto-fire button is held (see [Config::turbo_btn])
+    turbo_held: bool,
+    /// Frames passed since the auto-fire button was pressed
+    turbo_frame: u32,
+
+    /// Frames passed since the last user input
+    idle_frames: u32,
+    /// Button currently pressed by the attract mode (see [Config::attract])
+    attract_btn: Option<u8>,
+    /// Same seed presses the same buttons (see [Config::seed])
+    attract_rng: Rng,
+    /// Used to pick a random palette
+    palette_rng: Rng,
+    palette_fade: Option<PaletteFade>,
+
+    /// Input log being recorded or replayed, if any
+    input_log: Option<InputLog>,
+    /// Where the rendered frames are streamed, if anywhere
+    raw_output: Option<RawOutput>,
+    /// Number of emulated frames since the start
+    frame: u64,
+    /// Emulated frames since the game was loaded or restarted (see [Config::timer])
+    run_frames: u64,
+
+    /// Machine cycles left for the current frame (see [Config::accurate_timing])
+    /// May become negative, then the next frame gets less cycles
+    cycle_budget: i64,
+    /// Cycles that didn't fit in the previous frame, because the CPU was too slow
+    pending_cycles: u32,
+    /// Cycles executed since the last timers update, machine cycles with [Config::accurate_timing]
+    timer_cycles: i64,
+    /// Whether the previous frame was cut short (see [THROTTLE_BUDGET])
+    throttled: bool,
+    /// Cycles executed in the last frame, machine cycles with [Config::accurate_timing]
+    /// (see [Config::cpu_meter])
+    frame_cycles: i64,
+    /// Cycles the last frame was supposed to execute
+    frame_budget: i64,
+    /// Whether an instruction has failed in strict mode
+    exec_failed: bool,
+    /// Exit code of the process, if the app exited because the program has ended
+    /// (see [Config::exit_on_halt])
+    exit_code: Option<i32>,
+    context_error: Option<ContextError>,
+    /// Thread the main CPU runs on, `cpu` is then its latest copy (see [Config::threaded])
+    cpu_thread: Option<CpuThread>,
 }
 impl<'win> App<'win> {
-    pub fn new(config: Config, cpu: Cpu, buzzer: Option<Buzzer>) -> Self {
-        Self {
+    pub fn new(
+        config: Config,
+        cpu: Cpu,
+        compare: Option<Compare>,
+        buzzer: Option<Buzzer>,
+        game_path: PathBuf,
+        input_log: Option<InputLog>,
+        raw_output: Option<RawOutput>,
+    ) -> Self {
+        let game_modified = file_modified(&game_path);
+        let game_name = game_path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let game_size = cpu.rom_size();
+        let seed = config.seed;
+        // Display of the compared CPU is the same size
+        let len = cpu.display.len();
+
+        // Comparison and input log need both CPUs in the same frame, accurate timing needs the app's frames
+        let threaded = config.threaded && compare.is_none() && input_log.is_none() && !config.accurate_timing;
+        if config.threaded && !threaded {
+            eprintln!("--threaded doesn't work with --compare, --record, --replay and --accurate-timing, ignored");
+        }
+        let cpu_thread = threaded.then(|| CpuThread::spawn(cpu.clone()));
+
+        let mut app = Self {
             win: None,
             context: None,
 
+            ui: Ui::new(config.splash_frames, config.ui_scale),
             config,
             cpu,
+            compare,
+            input_target: InputTarget::Both,
             buzzer,
-            ui: Ui::new(),
-            screen: [None; DISPLAY_DATA_LEN],
+            screen: vec![None; len],
+            ui_bounds: None,
+            ui_dirty: None,
+            fade: vec![0; len],
+            prev_display: vec![false; len],
+            back: FrameBuffer::default(),
+            compare_back: FrameBuffer::default(),
+            glow_dirty: None,
+            highlight: None,
+            full_redraw: true,
+            title_dirty: true,
+            clear_deferred: false,
 
             is_paused: false,
             is_fastforward: false,
+            is_unfocused: false,
+            paused_by_unfocus: false,
+            paused_by_idle: false,
+            last_input: Instant::now(),
+            paused_ticks: 0,
+            blank_frames: Some(0),
 
-            last_time: Instant::now()
-        }
+            last_time: Instant::now(),
+
+            game_path,
+            game_name,
+            game_size,
+            game_modified,
+            last_watch_time: Instant::now(),
+
+            cursor_pos: PhysicalPosition::default(),
+            cursor_visible: true,
+            mouse_btn: None,
+
+            turbo_held: false,
+            turbo_frame: 0,
+
+            idle_frames: 0,
+            attract_btn: None,
+            attract_rng: Rng::new(seed),
+            palette_rng: Rng::new(seed),
+            palette_fade: None,
+
+            input_log,
+            raw_output,
+            frame: 0,
+            run_frames: 0,
+
+            cycle_budget: 0,
+            pending_cycles: 0,
+            timer_cycles: 0,
+            throttled: false,
+            frame_cycles: 0,
+            frame_budget: 0,
+            exec_failed: false,
+            exit_code: None,
+            context_error: None,
+            cpu_thread,
+        };
+
+        app.present();
+
+        // Replay should start with the same speed and random numbers
+        app.record(Event::Speed(app.config.speed));
+        app.record(Event::Seed(app.cpu.seed()));
+        app
     }
 
-    fn handle_key(&mut self, keycode: KeyCode, pressed: bool) {
-        if pressed {
-            match keycode {
-                // Next palette
-                KeyCode::BracketRight => self.config.next_palette(),
-                // Prev palette
-                KeyCode::BracketLeft => self.config.prev_palette(),
-
-                // Reset speed
-                KeyCode::Digit0 => self.set_speed(DEFAULT_SPEED),
-                // Increase speed
-                KeyCode::Equal | KeyCode::NumpadAdd => self.increase_speed(),
-                // Decrease speed
-                KeyCode::Minus | KeyCode::NumpadSubtract => self.decrease_speed(),
-                // Toggle mute
-                KeyCode::KeyM => self.buzzer_toggle_mute(),
-
-                // Toggle pause
-                KeyCode::Escape => self.is_paused ^= true,
-                // Enable fast forward
-                KeyCode::Space => self.is_fastforward = true,
-
-                // Restart the game and unpause (during the pause)
-                KeyCode::Enter if self.is_paused => {
-                    self.cpu.restart();
-                    self.is_paused = false;
-                },
+    /// Run the action bound to the pressed key
+    fn run_action(&mut self, action: Action, replaying: bool) {
+        match action {
+            // Pause key always toggles pause
+            Action::Pause => self.toggle_pause(),
+            // Enable fast forward
+            Action::FastForward => {
+                self.is_fastforward = true;
+                self.title_dirty = true;
+            }
+            // Toggle mute
+            Action::Mute => self.buzzer_toggle_mute(),
 
-                _ => ()
+            Action::NextPalette => {
+                let prev = self.config.palette.clone();
+                self.config.next_palette();
+                self.palette_changed(prev);
             }
-        } else {
+            Action::PrevPalette => {
+                let prev = self.config.palette.clone();
+                self.config.prev_palette();
+                self.palette_changed(prev);
+            }
+            Action::RandomPalette => {
+                let prev = self.config.palette.clone();
+                self.config.random_palette(&mut self.palette_rng);
+                self.palette_changed(prev);
+                self.ui.show_msg(format!(
+                    "palette {}/{}",
+                    self.config.cur_palette_index + 1,
+                    self.config.palettes.len(),
+                ));
+            }
+
+            // Replayed game keeps the recorded speed
+            Action::SpeedUp if !replaying => self.increase_speed(),
+            Action::SpeedDown if !replaying => self.decrease_speed(),
+            Action::ResetSpeed if !replaying => self.set_speed(DEFAULT_SPEED),
+
+            // Restart the game and unpause (during the pause or after the program end)
+            Action::Restart if !replaying && (self.is_paused || self.cpu.is_halted()) => {
+                self.reload_cpus();
+                self.record(Event::Restart);
+                self.is_paused = false;
+                self.title_dirty = true;
+            }
+
+            Action::HighlightDraws => {
+                self.config.highlight_draws ^= true;
+                self.ui.show_msg(if self.config.highlight_draws { "highlight on" } else { "highlight off" });
+            }
+            Action::Timer => {
+                self.config.timer ^= true;
+                self.ui.show_msg(if self.config.timer { "timer on" } else { "timer off" });
+            }
+            Action::CopyScreen => self.copy_screen(),
+
+            _ => ()
+        }
+    }
+
+    /// `repeat` is true for auto-repeated presses of a held key
+    fn handle_key(&mut self, keycode: KeyCode, pressed: bool, repeat: bool) {
+        // Any key skips the splash screen, but doesn't get into the game
+        if self.ui.is_splash() {
+            if pressed {
+                self.ui.skip_splash();
+            }
+            return;
+        }
+        if self.wake_up(pressed) { return }
+
+        // User is playing now
+        self.stop_attract();
+
+        // Replayed game is controlled only by the input log
+        let replaying = self.is_replaying();
+
+        // Remappable keys are checked before the fixed ones
+        let action = self.config.bindings.action(keycode);
+
+        if let (true, Some(action)) = (pressed, action) {
+            self.run_action(action, replaying);
+        } else if pressed {
             match keycode {
-                // Disable fast forward
-                KeyCode::Space => self.is_fastforward = false,
+                // Leave the fullscreen, just like everywhere else, or toggle pause
+                KeyCode::Escape => {
+                    if self.is_fullscreen() {
+                        self.toggle_fullscreen();
+                    } else {
+                        self.toggle_pause();
+                    }
+                }
+                // Toggle fullscreen
+                KeyCode::F11 => self.toggle_fullscreen(),
+
+                // Next draw strategy
+                KeyCode::F2 => {
+                    self.config.draw_strategy = self.config.draw_strategy.next();
+                    self.ui.show_msg(format!("draw {}", self.config.draw_strategy.name()));
+                    self.full_redraw = true;
+                }
+
+                // Numpad always changes the speed too
+                KeyCode::NumpadAdd if !replaying => self.increase_speed(),
+                KeyCode::NumpadSubtract if !replaying => self.decrease_speed(),
+
+                // Toggle vsync
+                KeyCode::F3 => {
+                    self.config.vsync ^= true;
+                    if let Some(ctx) = &mut self.context {
+                        ctx.set_vsync(self.config.vsync);
+                    }
+                    self.ui.show_msg(if self.config.vsync { "vsync on" } else { "vsync off" });
+                }
+
+                // Toggle the CPU load meter
+                KeyCode::F12 => {
+                    self.config.cpu_meter ^= true;
+                    self.ui.show_msg(if self.config.cpu_meter { "meter on" } else { "meter off" });
+                }
+
+                // Toggle the pixel grid
+                KeyCode::F5 => {
+                    self.config.grid ^= true;
+                    let grid = self.grid();
+                    if let Some(ctx) = &mut self.context {
+                        ctx.set_grid(grid);
+                    }
+                    self.ui.show_msg(if self.config.grid { "grid on" } else { "grid off" });
+                }
+
+                // Start new random numbers
+                KeyCode::F4 if !replaying => {
+                    let seed = rng::random_seed();
+                    self.set_seed(seed);
+                    self.record(Event::Seed(seed));
+                    self.ui.show_msg(format!("seed {}", seed));
+                    crate::message!("Random seed: {}", seed);
+                }
+
+                // Switch the CPUs controlled by the user in the comparison mode
+                KeyCode::Tab if self.compare.is_some() => {
+                    self.input_target = self.input_target.next();
+                    self.ui.show_msg(match self.input_target {
+                        InputTarget::Both => "input both",
+                        InputTarget::Left => "input left",
+                        InputTarget::Right => "input right",
+                    });
+                }
+
+                // Clear the display, but let the game run as if nothing happened
+                KeyCode::F6 => {
+                    self.with_cpu(Cpu::clear_display);
+                    if let Some(compare) = &mut self.compare {
+                        compare.cpu.clear_display();
+                    }
+                    self.ui.show_msg("display cleared");
+                }
+
+                // Print the registers to stdout
+                KeyCode::F7 => {
+                    crate::message!("{}", headless::registers_text(&self.cpu, &self.config.symbols));
+                    self.ui.show_msg("regs printed");
+                }
+
+                // Print the display to stdout, handy for bug reports
+                KeyCode::F8 => {
+                    crate::message!("{}", headless::display_text(&self.cpu, &self.config.symbols).trim_end_matches('\n'));
+                    self.ui.show_msg("display printed");
+                }
+
+                // Step a single instruction during the pause
+                KeyCode::F9 if self.can_step_paused() => {
+                    // Stepped instruction is printed, so the steps can be followed in the terminal
+                    let pc = self.cpu.pc();
+                    let byte = |addr: u16| self.cpu.memory().get(addr as usize).copied().unwrap_or(0);
+                    crate::message!("{}: {:02X}{:02X}", self.config.symbols.annotate(pc), byte(pc), byte(pc.wrapping_add(1)));
+
+                    self.step_cpu();
+                    self.ui.show_msg(format!("pc {:03x}", self.cpu.pc()));
+                }
+                // Tick the timers once during the pause
+                KeyCode::F10 if self.can_step_paused() => self.step_timers_paused(),
+
                 _ => ()
             }
+        } else if action == Some(Action::FastForward) {
+            // Disable fast forward
+            self.is_fastforward = false;
+            self.title_dirty = true;
         }
 
         // Change pressed button only if correct button was pressed
-        let Some(code) = key_to_btn(keycode) else {
+        // and it isn't taken by an action
+        let Some(code) = key_to_btn(keycode).filter(|_| action.is_none()) else {
             return;
         };
+        // Held button stays pressed until it is released, repeated presses would
+        // satisfy `Fx0A` again and again
+        if repeat || replaying {
+            return;
+        }
+
+        // Auto-fire button is toggled in [App::step_turbo] while held
+        if self.config.turbo_btn == Some(code) {
+            self.turbo_held = pressed;
+            self.turbo_frame = 0;
+        }
 
         if pressed {
-            self.cpu.button_pressed(code);
+            self.press_btn(code);
         } else {
-            self.cpu.button_released(code);
+            self.release_btn(code);
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.is_paused ^= true;
+        self.paused_by_unfocus = false;
+        self.paused_by_idle = false;
+        self.paused_ticks = 0;
+        self.title_dirty = true;
+
+        if self.is_paused {
+            self.paused();
+        }
+    }
+    /// Pause the game if the user hasn't touched anything for a while (see [Config::auto_pause])
+    fn step_auto_pause(&mut self) {
+        let Some(secs) = self.config.auto_pause else { return };
+        if self.is_paused || self.last_input.elapsed() < Duration::from_secs(secs as u64) { return }
+
+        self.is_paused = true;
+        self.paused_by_idle = true;
+        self.title_dirty = true;
+        self.paused();
+    }
+    /// Warn once if the game runs for a while without drawing anything (see [Config::blank_warning])
+    fn step_blank_warning(&mut self) {
+        // There is nothing to blame the quirks for without a game
+        if !self.cpu.is_ready() { return }
+        let (Some(secs), Some(frames)) = (self.config.blank_warning, &mut self.blank_frames) else { return };
+        *frames += 1;
+        if (*frames as u64) < secs as u64 * 1000 / TARGET_DELAY { return }
+
+        self.blank_frames = None;
+        self.ui.show_msg("no output?");
+        eprintln!("No display output for {} seconds, the game may need other quirks (see --quirks and --auto-quirks)", secs);
+    }
+    /// Called on every key and mouse button event
+    /// Returns whether the press has unpaused the game paused by [App::step_auto_pause],
+    /// then the press does nothing else
+    fn wake_up(&mut self, pressed: bool) -> bool {
+        self.last_input = Instant::now();
+        if !pressed || !self.paused_by_idle { return false }
+
+        self.is_paused = false;
+        self.paused_by_idle = false;
+        self.title_dirty = true;
+        true
+    }
+    /// Called when the game gets paused
+    /// Timers are frozen during the pause, so the rest of the biiip plays after it,
+    /// unless [Config::pause_clears_sound] is enabled
+    fn paused(&mut self) {
+        // Input log doesn't know about the pauses, the replayed game would go differently
+        if !self.config.pause_clears_sound || self.input_log.is_some() { return }
+
+        self.with_cpu(|cpu| cpu.set_sound_timer(0));
+        if let Some(compare) = &mut self.compare {
+            compare.cpu.set_sound_timer(0);
+        }
+    }
+    /// Whether the game can be stepped by hand
+    /// Input log doesn't know about such steps, so they are disabled while it is recorded or replayed,
+    /// and the CPU thread steps the CPU only by itself
+    fn can_step_paused(&self) -> bool {
+        self.is_paused && self.input_log.is_none() && self.cpu_thread.is_none()
+    }
+    /// Tick the timers of all the CPUs once, as if a 60Hz frame has passed
+    fn step_timers_paused(&mut self) {
+        self.cpu.step_timers();
+        if let Some(compare) = &mut self.compare {
+            compare.cpu.step_timers();
+        }
+        self.paused_ticks += 1;
+
+        // The tick count is shown in the pause box
+        self.ui.show_msg(format!("dt{} st{}", self.cpu.delay_timer(), self.cpu.sound_timer()));
+    }
+
+    /// Put the image of the displays with the current palette on the clipboard, without the UI
+    /// Image is saved into the current directory if there is no clipboard
+    fn copy_screen(&mut self) {
+        let displays: Vec<&Cpu> = std::iter::once(&self.cpu)
+            .chain(self.compare.as_ref().map(|c| &c.cpu))
+            .collect();
+        let (w, h) = (self.cpu.width(), self.cpu.height());
+        let width = w * displays.len() as u32 * DISPLAY_SCALE;
+        let height = h * DISPLAY_SCALE;
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let cpu = displays[(x / DISPLAY_SCALE / w) as usize];
+                let i = (y / DISPLAY_SCALE * w + x / DISPLAY_SCALE % w) as usize;
+                let color = self.config.plane_color(cpu.pixel_planes(i));
+                pixels.extend_from_slice(&[color.0, color.1, color.2, 255]);
+            }
+        }
+        let png = png::encode(width, height, &pixels);
+
+        let err = match clipboard::copy_png(&png) {
+            Ok(()) => {
+                self.ui.show_msg("screen copied");
+                return;
+            }
+            Err(e) => e,
+        };
+
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = PathBuf::from(format!("pitch1002-{}.png", secs));
+        match fs::write(&path, &png) {
+            Ok(()) => {
+                crate::message!("Unable to copy the screen ({}), saved it to {}", err, path.display());
+                self.ui.show_msg("screen saved");
+            }
+            Err(e) => {
+                eprintln!("Unable to copy the screen ({}) or save it to {}: {}", err, path.display(), e);
+                self.ui.show_msg("error! see terminal");
+            }
+        }
+    }
+
+    /// Color and opacity of the pixel grid, if it is shown
+    fn grid(&self) -> Option<(Color, f32)> {
+        self.config.grid.then(|| (self.config.grid_color.clone(), self.config.grid_opacity))
+    }
+
+    // Window
+    fn is_fullscreen(&self) -> bool {
+        self.win.as_ref().is_some_and(|win| win.fullscreen().is_some())
+    }
+    fn toggle_fullscreen(&mut self) {
+        let Some(win) = &self.win else { return };
+
+        let fullscreen =
+            if win.fullscreen().is_some() { None }
+            else { Some(Fullscreen::Borderless(None)) };
+        win.set_fullscreen(fullscreen);
+    }
+    /// Position of the window with the `size` (see [Config::window_pos])
+    /// Returns `None` if it is up to the OS
+    fn window_position(&self, event_loop: &ActiveEventLoop, size: LogicalSize<u32>) -> Option<PhysicalPosition<i32>> {
+        match self.config.window_pos {
+            WindowPos::Default => None,
+            WindowPos::Center => {
+                let monitor = event_loop.primary_monitor()
+                    .or_else(|| event_loop.available_monitors().next())?;
+                let win_size: PhysicalSize<i32> = size.to_physical(monitor.scale_factor());
+                let (pos, mon_size) = (monitor.position(), monitor.size());
+
+                Some(PhysicalPosition::new(
+                    pos.x + (mon_size.width as i32 - win_size.width) / 2,
+                    pos.y + (mon_size.height as i32 - win_size.height) / 2,
+                ))
+            }
+            WindowPos::At(x, y) => {
+                // Top left corner must be on one of the monitors
+                let visible = event_loop.available_monitors().any(|monitor| {
+                    let (pos, size) = (monitor.position(), monitor.size());
+                    (pos.x..pos.x + size.width as i32).contains(&x)
+                        && (pos.y..pos.y + size.height as i32).contains(&y)
+                });
+                if !visible {
+                    eprintln!("Window position {},{} is off-screen, ignored", x, y);
+                    return None;
+                }
+
+                Some(PhysicalPosition::new(x, y))
+            }
+        }
+    }
+
+    // Buttons
+    /// Press the CHIP-8 button and record it into the input log
+    fn press_btn(&mut self, btn: u8) {
+        self.set_btn(btn, true);
+        self.record(Event::Press(btn));
+    }
+    /// Release the CHIP-8 button and record it into the input log
+    fn release_btn(&mut self, btn: u8) {
+        self.set_btn(btn, false);
+        self.record(Event::Release(btn));
+    }
+    /// Release every held button of all the CPUs and record it into the input log
+    /// Key releases don't reach the window while it is out of focus, so the keys would get stuck
+    fn release_held_btns(&mut self) {
+        // Replayed game is controlled only by the input log
+        if self.is_replaying() { return }
+
+        self.turbo_held = false;
+        self.mouse_btn = None;
+
+        for btn in 0..16 {
+            let held = self.cpu.is_btn_pressed(btn)
+                || self.compare.as_ref().is_some_and(|c| c.cpu.is_btn_pressed(btn));
+            if held {
+                self.record(Event::Release(btn));
+            }
+        }
+
+        self.with_cpu(Cpu::release_all_buttons);
+        if let Some(compare) = &mut self.compare {
+            compare.cpu.release_all_buttons();
+        }
+    }
+    /// Press or release the button of the CPUs controlled by the user (see [InputTarget])
+    fn set_btn(&mut self, btn: u8, pressed: bool) {
+        let target = self.input_target;
+        let set = move |cpu: &mut Cpu| {
+            if pressed {
+                cpu.button_pressed(btn);
+            } else {
+                cpu.button_released(btn);
+            }
+        };
+
+        if target != InputTarget::Right {
+            self.with_cpu(set);
+        }
+        if let Some(compare) = self.compare.as_mut().filter(|_| target != InputTarget::Left) {
+            set(&mut compare.cpu);
+        }
+    }
+
+    /// Toggle the auto-fire button while it is held (see [Config::turbo_btn])
+    /// Every toggle is a real press/release, so each press satisfies one `Fx0A` wait
+    fn step_turbo(&mut self) {
+        let Some(btn) = self.config.turbo_btn else { return };
+        if !self.turbo_held || self.is_replaying() { return }
+
+        // Number of frames between toggles, one press/release pair per turbo period
+        let half_period = (30 / self.config.turbo_rate as u32).max(1);
+
+        self.turbo_frame += 1;
+        if self.turbo_frame.is_multiple_of(half_period) {
+            if (self.turbo_frame / half_period) % 2 == 1 {
+                self.release_btn(btn);
+            } else {
+                self.press_btn(btn);
+            }
         }
     }
 
+    /// Press random buttons if the user hasn't touched anything for a while (see [Config::attract])
+    fn step_attract(&mut self) {
+        if !self.config.attract || self.is_replaying() { return }
+
+        self.idle_frames = self.idle_frames.saturating_add(1);
+        if self.idle_frames < ATTRACT_IDLE_FRAMES { return }
+
+        if (self.idle_frames - ATTRACT_IDLE_FRAMES).is_multiple_of(ATTRACT_PRESS_FRAMES) {
+            if let Some(btn) = self.attract_btn.take() {
+                self.release_btn(btn);
+            } else {
+                let btn = self.attract_rng.below(16) as u8;
+                self.attract_btn = Some(btn);
+                self.press_btn(btn);
+            }
+        }
+    }
+    /// Give the control back to the user
+    fn stop_attract(&mut self) {
+        self.idle_frames = 0;
+
+        if let Some(btn) = self.attract_btn.take() {
+            self.release_btn(btn);
+        }
+    }
+
+    /// Press or release the CHIP-8 button under the cursor
+    /// The window is split into 4x4 cells, one for each button of the keypad
+    fn handle_mouse(&mut self, pressed: bool) {
+        if self.wake_up(pressed) { return }
+        self.stop_attract();
+
+        if !pressed {
+            if let Some(btn) = self.mouse_btn.take() {
+                self.release_btn(btn);
+            }
+            return;
+        }
+        if self.is_replaying() || self.ui.is_splash() { return }
+
+        let Some(win) = &self.win else { return };
+        let size = win.inner_size();
+        if size.width == 0 || size.height == 0 { return }
+
+        // Each display of the comparison mode has its own keypad
+        let width = size.width as f64 / self.displays() as f64;
+        let col = (self.cursor_pos.x % width / width * 4.0).clamp(0.0, 3.0) as usize;
+        let row = (self.cursor_pos.y / size.height as f64 * 4.0).clamp(0.0, 3.0) as usize;
+        let btn = KEYPAD[row][col];
+
+        // Release the previous button, just in case
+        if let Some(prev) = self.mouse_btn.replace(btn) {
+            self.release_btn(prev);
+        }
+        self.press_btn(btn);
+    }
+
+    /// `prev` is the palette before the change
+    fn palette_changed(&mut self, prev: Palette) {
+        crate::debug!("Palette {}: {:?}", self.config.cur_palette_index, self.config.palette);
+        self.full_redraw = true;
+        self.title_dirty = true;
+        if let Some(ctx) = &mut self.context {
+            ctx.set_clear_color(&self.config.palette.border());
+        }
+
+        if self.config.palette_fade > 0 {
+            // Palette may change in the middle of the fade, then it continues from the shown colors
+            let from = self.faded_palette().unwrap_or(prev);
+            self.palette_fade = Some(PaletteFade { from, frame: 0 });
+        }
+    }
+    /// Colors of the game in the middle of the palette fade, if it is fading
+    fn faded_palette(&self) -> Option<Palette> {
+        let fade = self.palette_fade.as_ref()?;
+        let t = fade.frame as f32 / self.config.palette_fade as f32;
+        let palette = &self.config.palette;
+
+        Some(Palette {
+            fg: fade.from.fg.mix_linear(&palette.fg, t),
+            bg: fade.from.bg.mix_linear(&palette.bg, t),
+            ..palette.clone()
+        })
+    }
+    /// Move the palette fade one frame further
+    fn step_palette_fade(&mut self) {
+        let Some(fade) = &mut self.palette_fade else { return };
+
+        fade.frame += 1;
+        if fade.frame >= self.config.palette_fade {
+            self.palette_fade = None;
+        }
+        // Every game pixel changes its color
+        self.full_redraw = true;
+    }
+
     // Speed
-    pub fn set_speed(&mut self, speed: u16) {
-        self.config.speed = speed.clamp(1, MAX_SPEED);
-        self.ui.speed_msg_timer = 30;
+    pub fn set_speed(&mut self, speed: u32) {
+        // Only the brave go beyond the max speed
+        let max = if self.config.unsafe_speed { u32::MAX } else { MAX_SPEED };
+        self.config.speed = speed.clamp(1, max);
+        self.ui.show_msg(format!("speed {}", self.config.speed));
+        self.title_dirty = true;
+        self.record(Event::Speed(self.config.speed));
     }
     pub fn increase_speed(&mut self) {
-        self.set_speed(self.config.speed + 1);
+        self.set_speed(self.config.speed.saturating_add(1));
     }
     pub fn decrease_speed(&mut self) {
         self.set_speed(self.config.speed.saturating_sub(1));
@@ -157,45 +990,529 @@ impl<'win> App<'win> {
             buz.set_playing(state);
         }
     }
+    pub fn buzzer_set_pitch(&mut self, pitch: f32) {
+        if let Some(buz) = &mut self.buzzer {
+            buz.set_pitch(pitch);
+        }
+    }
     pub fn buzzer_toggle_mute(&mut self) {
         if let Some(buz) = &mut self.buzzer {
             buz.set_muted(!buz.muted);
         }
     }
 
+    // Input log
+    fn is_replaying(&self) -> bool {
+        matches!(self.input_log, Some(InputLog::Replay(_)))
+    }
+    /// Write the event into the input log, if it is being recorded
+    /// It will be replayed right before the next emulated frame
+    fn record(&mut self, event: Event) {
+        let Some(InputLog::Record(recorder)) = &mut self.input_log else { return };
+
+        if let Err(e) = recorder.record(self.frame, event) {
+            eprintln!("Unable to record the input: {}", e);
+            eprintln!("Recording stopped");
+            self.input_log = None;
+        }
+    }
+    /// Apply the replayed events of the next emulated frame
+    /// The user gets the control back after the last event
+    fn replay_events(&mut self) {
+        let Some(InputLog::Replay(replay)) = &mut self.input_log else { return };
+
+        let events = replay.take_events(self.frame);
+        if replay.is_finished() {
+            self.input_log = None;
+            self.ui.show_msg("replay ended");
+        }
+
+        for event in events {
+            match event {
+                Event::Press(btn) => self.set_btn(btn, true),
+                Event::Release(btn) => self.set_btn(btn, false),
+                Event::Speed(speed) => self.set_speed(speed),
+                Event::Seed(seed) => self.set_seed(seed),
+                Event::Restart => self.reload_cpus(),
+            }
+        }
+    }
+
+    // Focus
+    fn handle_focus(&mut self, focused: bool) {
+        self.is_unfocused = !focused;
+
+        if !self.config.pause_on_unfocus { return }
+
+        if !focused && !self.is_paused {
+            self.is_paused = true;
+            self.paused_by_unfocus = true;
+            self.title_dirty = true;
+            self.paused();
+        } else if focused && self.paused_by_unfocus {
+            // Don't unpause the game paused by the user
+            self.is_paused = false;
+            self.paused_by_unfocus = false;
+            self.title_dirty = true;
+        }
+    }
+
+    /// Hide the cursor while the game is running, it just gets in the way
+    fn update_cursor(&mut self) {
+        let Some(win) = &self.win else { return };
+
+        let visible = self.config.show_cursor
+            || self.config.mouse_keypad
+            || self.is_paused
+            || self.ui.is_splash()
+            || self.cpu.is_halted();
+        if visible == self.cursor_visible { return }
+
+        win.set_cursor_visible(visible);
+        self.cursor_visible = visible;
+    }
+
+    /// Update the window title, if the state shown in it has changed
+    /// For example: "PITCH1002 — game.ch8 (512 bytes) — speed 20 — palette 3/15 — paused"
+    fn update_title(&mut self) {
+        if !self.title_dirty { return }
+        let Some(win) = &self.win else { return };
+
+        let mut title = String::from("PITCH1002");
+        title.push_str(&format!(" — {}", self.game_info()));
+        title.push_str(&format!(" — speed {}", self.config.speed));
+        title.push_str(&format!(
+            " — palette {}/{}",
+            self.config.cur_palette_index + 1,
+            self.config.palettes.len()
+        ));
+
+        if self.is_paused {
+            title.push_str(" — paused");
+        } else if self.is_fastforward {
+            title.push_str(" — fast forward");
+        }
+
+        win.set_title(&title);
+        self.title_dirty = false;
+    }
+
+    /// Save the preferences to restore them on the next launch
+    fn save_state(&self) {
+        let state = SavedState {
+            palette_index: Some(self.config.cur_palette_index),
+            speed: Some(self.config.speed),
+            muted: self.buzzer.as_ref().map(|buz| buz.muted),
+        };
+
+        if let Err(e) = state.save() {
+            eprintln!("Unable to save the preferences: {}", e);
+        }
+    }
+
+    // Game
+    /// Name and size of the currently running game, like "game.ch8 (512 bytes)"
+    pub fn game_info(&self) -> String {
+        format!("{} ({} bytes)", self.game_name, self.game_size)
+    }
+
+    // Watch
+    /// Reload the game if its file was modified since the last check
+    fn check_game_modified(&mut self) {
+        let modified = file_modified(&self.game_path);
+        if modified.is_none() || modified == self.game_modified {
+            return;
+        }
+        self.game_modified = modified;
+
+        match rom::read(&self.game_path) {
+            Ok(game) => {
+                // The new game starts with all the buttons released, even if the keys are still held
+                self.release_held_btns();
+                // Fresh CPU marks the whole display as changed, so there's no need to redraw
+                let main_game = game.clone();
+                self.with_cpu(move |cpu| {
+                    cpu.unload();
+                    cpu.load(&main_game);
+                });
+                if let Some(compare) = self.compare.as_mut().filter(|c| c.same_game) {
+                    compare.cpu.unload();
+                    compare.cpu.load(&game);
+                }
+                self.game_size = game.len();
+                self.blank_frames = Some(0);
+                self.run_frames = 0;
+                self.title_dirty = true;
+                self.ui.show_msg("reloaded");
+                crate::message!("Reloaded {}", self.game_info());
+            }
+            // The file may be in the middle of being rewritten, so just try again later
+            Err(e) => eprintln!("Unable to reload the game: {}", e),
+        }
+    }
+
+    // Cpu
+    /// Number of the CHIP-8 displays shown in the window
+    fn displays(&self) -> u32 {
+        if self.compare.is_some() { 2 } else { 1 }
+    }
+    /// Show the current displays of the CPUs on the next render (see [FrameBuffer])
+    fn present(&mut self) {
+        self.back.present(&mut self.cpu);
+        if let Some(compare) = &mut self.compare {
+            self.compare_back.present(&mut compare.cpu);
+        }
+    }
+    /// Change the main CPU, on its thread if it runs there (see [Config::threaded])
+    /// The change shows up in `cpu` with the next copy from the thread
+    fn with_cpu(&mut self, f: impl FnOnce(&mut Cpu) + Send + 'static) {
+        match &self.cpu_thread {
+            Some(thread) => thread.run(f),
+            None => f(&mut self.cpu),
+        }
+    }
+    /// Take the latest copy of the CPU from its thread and tell the thread how fast to run
+    fn sync_cpu_thread(&mut self) {
+        let running = !self.is_paused && !self.ui.is_splash();
+        let frames = if self.is_fastforward { FASTFORWARD_FRAMES as u32 } else { 1 };
+        let speed = running.then(|| (self.config.speed.saturating_mul(frames), self.config.speed));
+        let Some(thread) = &mut self.cpu_thread else { return };
+        thread.set_speed(speed);
+
+        while let Some(snapshot) = thread.try_snapshot() {
+            self.cpu = snapshot.cpu;
+            // Changed regions of the skipped copies are lost, the screen is small anyway
+            self.full_redraw = true;
+            self.frame_cycles = snapshot.cycles;
+            self.frame_budget = snapshot.budget;
+
+            if snapshot.display_changed {
+                self.blank_frames = None;
+            }
+            if snapshot.failed {
+                self.ui.show_msg("error! see terminal");
+                self.exec_failed = true;
+            }
+        }
+    }
+    /// Start the random numbers of all the CPUs and of the attract mode over from the `seed`
+    fn set_seed(&mut self, seed: u32) {
+        self.attract_rng = Rng::new(seed);
+        self.with_cpu(move |cpu| cpu.set_seed(seed));
+        if let Some(compare) = &mut self.compare {
+            compare.cpu.set_seed(seed);
+        }
+    }
+    /// Restart the game on all the CPUs
+    fn reload_cpus(&mut self) {
+        self.with_cpu(Cpu::reload);
+        if let Some(compare) = &mut self.compare {
+            compare.cpu.reload();
+        }
+        self.blank_frames = Some(0);
+        self.run_frames = 0;
+    }
+
+    fn step_cpu(&mut self) {
+        // Compared CPU makes exactly the same number of steps
+        let compare_changed = match self.compare.as_mut().map(|c| c.cpu.step()) {
+            Some(Ok(res)) => res.display_changed,
+            Some(Err(e)) => {
+                eprintln!("Execution error (right): {}", e);
+                self.ui.show_msg("error! see terminal");
+                false
+            }
+            None => false,
+        };
+
+        let display_changed = match self.cpu.step() {
+            Ok(res) => res.display_changed,
+            Err(e) => {
+                eprintln!("Execution error: {}", e);
+                self.ui.show_msg("error! see terminal");
+                self.exec_failed = true;
+                false
+            }
+        };
+
+        if display_changed || compare_changed {
+            self.blank_frames = None;
+        }
+
+        // Step draw strategy
+        if self.config.draw_strategy == DrawStrategy::Step && (display_changed || compare_changed) {
+            self.present();
+            self.render_screen();
+        }
+    }
+    /// Step the cpu `speed` times, the cycles that don't fit in the frame are left for the next one
+    fn step_cpu_frame(&mut self) {
+        let cycles = self.config.speed.saturating_add(self.pending_cycles);
+        self.pending_cycles = 0;
+        self.frame_budget = self.config.speed as i64;
+        self.frame_cycles = 0;
+
+        for done in 0..cycles {
+            if done % THROTTLE_CHECK_CYCLES == 0 && self.should_throttle() {
+                // The game just runs slower, if it can't keep up even with that
+                self.pending_cycles = (cycles - done).min(self.config.speed);
+                return;
+            }
+            self.step_cpu();
+            self.count_timer_cycles(1, self.config.speed as i64);
+            self.frame_cycles += 1;
+        }
+        self.set_throttled(false);
+    }
+    /// Step the cpu until the machine cycles of the frame run out (see [Config::accurate_timing])
+    /// Default speed is the speed of the COSMAC VIP
+    fn step_cpu_accurate(&mut self) {
+        let cycles = timing::CYCLES_PER_FRAME as i64 * self.config.speed as i64 / DEFAULT_SPEED as i64;
+        // At most one frame of the cycles is carried if the CPU is too slow
+        self.cycle_budget = self.cycle_budget.min(cycles) + cycles;
+        self.frame_budget = cycles;
+        self.frame_cycles = 0;
+
+        let mut done = 0;
+        while self.cycle_budget > 0 {
+            if done % THROTTLE_CHECK_CYCLES == 0 && self.should_throttle() { return }
+            done += 1;
+
+            let cost = timing::cost(self.cpu.next_instruction()) as i64;
+            self.cycle_budget -= cost;
+            self.step_cpu();
+            self.count_timer_cycles(cost, cycles);
+            self.frame_cycles += cost;
+        }
+        self.set_throttled(false);
+    }
+    /// Update the timers every `period` executed cycles, so they count down at 60 Hz of the
+    /// emulated time, even if the frame was cut short (see [App::should_throttle])
+    fn count_timer_cycles(&mut self, cycles: i64, period: i64) {
+        self.timer_cycles += cycles;
+
+        if self.timer_cycles >= period {
+            // Only one update even if the speed has just decreased a lot
+            self.timer_cycles = (self.timer_cycles - period) % period;
+
+            self.cpu.step_timers();
+            if let Some(compare) = &mut self.compare {
+                compare.cpu.step_timers();
+            }
+        }
+    }
+    /// Whether the CPU took too long in this frame and should stop, so the window stays responsive
+    fn should_throttle(&mut self) -> bool {
+        // Replayed game must get exactly the same cycles in every frame
+        if self.input_log.is_some() { return false }
+
+        let throttle = self.last_time.elapsed() >= THROTTLE_BUDGET;
+        if throttle {
+            self.set_throttled(true);
+        }
+        throttle
+    }
+    fn set_throttled(&mut self, throttled: bool) {
+        if throttled == self.throttled { return }
+        self.throttled = throttled;
+
+        if throttled {
+            crate::debug!("Throttling: the CPU can't keep up with speed {}", self.config.speed);
+        } else {
+            crate::debug!("Throttling stopped");
+        }
+    }
+
+    /// Exit code of the process after the app has exited, if it should be changed
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+    /// Why the rendering couldn't start, then the app has exited right away
+    pub fn take_context_error(&mut self) -> Option<ContextError> {
+        self.context_error.take()
+    }
+    /// Save everything that should be saved and close the app
+    fn exit(&mut self, event_loop: &ActiveEventLoop) {
+        if self.config.remember {
+            self.save_state();
+        }
+        if self.config.log_ignored {
+            print_ignored(&self.cpu);
+        }
+        if let Some(path) = &self.config.dump_mem {
+            match fs::write(path, self.cpu.memory()) {
+                Ok(()) => crate::message!("Memory dumped to {}", path.display()),
+                Err(e) => eprintln!("Unable to dump the memory to {}: {}", path.display(), e),
+            }
+        }
+        if let Some(InputLog::Record(recorder)) = &mut self.input_log {
+            if let Err(e) = recorder.flush() {
+                eprintln!("Unable to record the input: {}", e);
+            }
+        }
+        event_loop.exit();
+    }
+
+    /// Stream the current frame, once in a frame whatever the draw strategy is
+    fn write_raw_frame(&mut self) {
+        let (Some(raw), Some(ctx)) = (&mut self.raw_output, &self.context) else { return };
+
+        if let Err(e) = raw.write_frame(ctx.buffer(), ctx.width()) {
+            eprintln!("Unable to write the raw output: {}", e);
+            eprintln!("Raw output stopped");
+            self.raw_output = None;
+        }
+    }
+
+    /// Update the intensity of the pixels (see [DrawStrategy::Fade])
+    /// Returns whether any pixel has changed
+    fn step_fade(&mut self) -> bool {
+        let mut changed = false;
+
+        for (i, intensity) in self.fade.iter_mut().enumerate() {
+            let on = self.back.is_on(i);
+            let new =
+                if on { 255 }
+                else { intensity.saturating_sub(FADE_STEP) };
+
+            changed |= new != *intensity;
+            *intensity = new;
+        }
+
+        changed
+    }
+
     fn render_screen(&mut self) {
+        let fading = self.config.draw_strategy == DrawStrategy::Fade;
+        // Fading pixels change every frame, even if the game didn't draw anything
+        if fading && self.step_fade() {
+            self.full_redraw = true;
+        }
+
+        // Only the game pixels fade into the new palette, UI is drawn with it right away
+        let faded_palette = self.faded_palette();
         let ctx = self.context.as_mut().unwrap();
 
-        // Copy screen data to render buffer
-        for i in 0..DISPLAY_DATA_LEN {
-            let pixel = self.screen[i].unwrap_or(self.cpu.display[i]);
+        // Keep showing the previous screen until something is drawn after the clear,
+        // but not longer than a frame, the game may really want an empty screen
+        if self.config.defer_clear && self.back.clear_pending && !self.clear_deferred {
+            ctx.render();
+            return;
+        }
+
+        // Update only the changed part of the screen
+        let dirty = Rect::merge(self.back.dirty.take(), self.ui_dirty.take());
+        let dirty = Rect::merge(dirty, self.glow_dirty.take());
+        // Previous outline is erased and the new one is drawn
+        let last_draw = self.back.last_draw.take().filter(|_| self.config.highlight_draws);
+        let dirty = Rect::merge(dirty, std::mem::replace(&mut self.highlight, last_draw));
+        let dirty = Rect::merge(dirty, last_draw);
+        let highlighted = |x: u32, y: u32| last_draw.is_some_and(|r| {
+            let inside = x >= r.x && x < r.x + r.w && y >= r.y && y < r.y + r.h;
+            inside && (x == r.x || x == r.x + r.w - 1 || y == r.y || y == r.y + r.h - 1)
+        });
+        // Fading already leaves a trail of the turned off pixels
+        let afterglow = self.config.afterglow && !fading;
+        let (width, height) = (self.cpu.width(), self.cpu.height());
+        let full = self.full_redraw;
+        let config = &self.config;
+        let game_color = |planes: u8| match &faded_palette {
+            Some(palette) => config.palette_plane_color(palette, planes),
+            None => config.plane_color(planes),
+        };
+        let dirty =
+            if full { Some(Rect::full(width, height)) }
+            else { dirty };
+        self.full_redraw = false;
+
+        if let Some(rect) = dirty {
+            // Copy changed screen data to render buffer
+            for y in rect.y..rect.y + rect.h {
+                for x in rect.x..rect.x + rect.w {
+                    let i = (y * width + x) as usize;
+                    let planes = match self.screen[i] {
+                        Some(pixel) => u8::from(pixel),
+                        None => self.back.planes[i],
+                    };
+
+                    // Pixel turned off since the previous render glows for one more render
+                    let glowing = afterglow
+                        && self.screen[i].is_none()
+                        && self.prev_display[i]
+                        && !self.back.is_on(i);
+
+                    // RGB color
+                    // UI stays on top of the outline
+                    let color =
+                        if self.screen[i].is_none() && highlighted(x, y) { HIGHLIGHT_COLOR }
+                        else if glowing {
+                            self.glow_dirty = Rect::merge(self.glow_dirty, Some(Rect::pixel(x, y)));
+                            game_color(0).mix_linear(&game_color(1), AFTERGLOW_INTENSITY)
+                        }
+                        else if fading && self.screen[i].is_none() {
+                            let t = self.fade[i] as f32 / 255.0;
+                            let (off, on) = (game_color(0), game_color(planes.max(1)));
+
+                            if config.fast_fade { off.mix(&on, t) }
+                            else { off.mix_linear(&on, t) }
+                        }
+                        else if let Some(pixel) = self.screen[i] { config.ui_color(pixel) }
+                        else { game_color(planes) };
+
+                    ctx.set_pixel(x, y, &color);
+                }
+            }
+
+            ctx.write_buf(rect);
+        }
+        if afterglow {
+            for (i, prev) in self.prev_display.iter_mut().enumerate() {
+                *prev = self.back.is_on(i);
+            }
+        }
 
-            // RGB color
-            let color =
-                if pixel { self.config.fg() }
-                else { self.config.bg() };
+        // Compared display is drawn without any effects, they would only get in the way
+        if self.compare.is_some() {
+            let dirty = self.compare_back.dirty.take();
+            let dirty =
+                if full { Some(Rect::full(width, height)) }
+                else { dirty };
 
-            ctx.buffer_data[i*4 + 0] = color.0; // Red
-            ctx.buffer_data[i*4 + 1] = color.1; // Green
-            ctx.buffer_data[i*4 + 2] = color.2; // Blue
-            ctx.buffer_data[i*4 + 3] = 255; // Alpha
+            if let Some(rect) = dirty {
+                for y in rect.y..rect.y + rect.h {
+                    for x in rect.x..rect.x + rect.w {
+                        let i = (y * width + x) as usize;
+                        let color = game_color(self.compare_back.planes[i]);
+                        ctx.set_pixel(width + x, y, &color);
+                    }
+                }
+
+                ctx.write_buf(Rect { x: width + rect.x, ..rect });
+            }
         }
 
         // Render the screen
-        ctx.write_buf();
         ctx.render();
     }
 }
 impl<'win> ApplicationHandler for App<'win> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         // Create a window
-        let size = LogicalSize::new(DISPLAY_WIDTH * DISPLAY_SCALE, DISPLAY_HEIGHT * DISPLAY_SCALE);
+        let size = LogicalSize::new(
+            self.cpu.width() * DISPLAY_SCALE * self.displays(),
+            self.cpu.height() * DISPLAY_SCALE,
+        );
         let attrs = Window::default_attributes()
             .with_title("PITCH1002")
+            .with_window_icon(window_icon())
             .with_inner_size(size)
             .with_min_inner_size(size)
             .with_max_inner_size(size)
             .with_resizable(false);
+        let attrs = match self.window_position(event_loop, size) {
+            Some(pos) => attrs.with_position(pos),
+            None => attrs,
+        };
 
         #[cfg(target_os = "linux")]
         let attrs = {
@@ -205,7 +1522,29 @@ impl<'win> ApplicationHandler for App<'win> {
         };
 
         let win = Arc::new(event_loop.create_window(attrs).unwrap());
-        let mut context = Context::new(Arc::clone(&win));
+        let (width, height) = (self.cpu.width() * self.displays(), self.cpu.height());
+        let mut context: Box<dyn Renderer> = match &self.config.software {
+            Some(path) => Box::new(SoftwareRenderer::new(path.clone(), DISPLAY_SCALE, width, height)),
+            None => match Context::new(
+                Arc::clone(&win),
+                self.config.vsync,
+                self.config.backend,
+                self.config.smooth,
+                width,
+                height,
+            ) {
+                Ok(context) => Box::new(context),
+                // Nothing can be shown without the rendering, the error is printed after the exit
+                Err(e) => {
+                    self.context_error = Some(e);
+                    event_loop.exit();
+                    return;
+                }
+            },
+        };
+
+        context.set_grid(self.grid());
+        context.set_clear_color(&self.config.palette.border());
 
         // First time render
         context.render();
@@ -229,43 +1568,86 @@ impl<'win> ApplicationHandler for App<'win> {
                 if elapsed_ms >= TARGET_DELAY {
                     self.last_time = Instant::now();
 
+                    self.sync_cpu_thread();
+                    self.step_palette_fade();
                     self.draw_ui();
+                    self.update_title();
+                    self.update_cursor();
+
+                    if !self.ui.is_splash() {
+                        self.step_auto_pause();
+                    }
 
-                    if self.is_paused {
+                    if self.ui.is_splash() {
+                        // The game starts right after the splash screen
+                        self.ui.step_splash();
+                        self.present();
+                        self.render_screen();
+                    } else if self.is_paused {
                         // Simply render the screen if paused
+                        self.present();
                         self.render_screen();
                         self.buzzer_set_playing(false);
                     } else {
                         let speed = 
-                            if self.is_fastforward { 2 }
+                            if self.is_fastforward { FASTFORWARD_FRAMES }
                             else { 1 };
 
                         for _ in 0..speed {
-                            // Step cpu only if unpaused
-                            for _ in 0..self.config.speed {
-                                self.cpu.step();
+                            self.replay_events();
+                            self.step_turbo();
+                            self.step_attract();
 
-                                // Step draw strategy
-                                if self.config.draw_strategy == DrawStrategy::Step {
-                                    self.render_screen();
+                            // Step cpu only if unpaused, the CPU thread steps it by itself
+                            if self.cpu_thread.is_none() {
+                                if self.config.accurate_timing {
+                                    self.step_cpu_accurate();
+                                } else {
+                                    self.step_cpu_frame();
                                 }
                             }
 
-                            // Update the timers
-                            self.cpu.step_timers();
+                            self.step_blank_warning();
+                            self.frame += 1;
+                            self.run_frames += 1;
                         }
 
-                        if self.cpu.st > 0 {
-                            self.buzzer_set_playing(true);
-                        } else {
-                            self.buzzer_set_playing(false);
-                        }
+                        // Fast forwarding may turn a short biiip into a continuous tone
+                        let ff_audio =
+                            if self.is_fastforward { self.config.ff_audio }
+                            else { FastForwardAudio::Normal };
+
+                        self.buzzer_set_pitch(
+                            if ff_audio == FastForwardAudio::Pitch { FASTFORWARD_FRAMES as f32 }
+                            else { 1.0 }
+                        );
+                        let unfocus_mute = self.is_unfocused && self.config.mute_on_unfocus;
+                        let sound = self.cpu.sound_timer() > 0
+                            || self.compare.as_ref().is_some_and(|c| c.cpu.sound_timer() > 0);
+                        self.buzzer_set_playing(
+                            sound
+                            && ff_audio != FastForwardAudio::Mute
+                            && !unfocus_mute
+                        );
 
                         // Frame draw strategy
-                        if self.config.draw_strategy == DrawStrategy::Frame {
+                        // Step strategy renders by itself, but the changes that aren't draws are presented too
+                        self.present();
+                        if self.config.draw_strategy != DrawStrategy::Step {
                             self.render_screen();
                         }
+
+                        // Clear still pending at the end of the frame won't be delayed anymore
+                        self.clear_deferred = self.back.clear_pending;
+
+                        // Failed program ends with an error code
+                        if self.config.exit_on_halt && self.cpu.is_halted() {
+                            self.exit_code = Some(if self.exec_failed { 1 } else { 0 });
+                            self.exit(event_loop);
+                        }
                     }
+
+                    self.write_raw_frame();
                 }
 
                 // Set a delay between redraw requests
@@ -274,10 +1656,34 @@ impl<'win> ApplicationHandler for App<'win> {
                 event_loop.set_control_flow(ControlFlow::WaitUntil(wait));
             }
             WindowEvent::KeyboardInput {
-                event: KeyEvent { physical_key, state, .. },
+                event: KeyEvent { physical_key, state, repeat, .. },
                 ..
             } => if let PhysicalKey::Code(keycode) = physical_key {
-                self.handle_key(keycode, state == ElementState::Pressed)
+                self.handle_key(keycode, state == ElementState::Pressed, repeat)
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_pos = position;
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } if self.config.mouse_keypad => {
+                self.handle_mouse(state == ElementState::Pressed);
+            }
+            WindowEvent::MouseInput { state, .. } => {
+                self.wake_up(state == ElementState::Pressed);
+            }
+            WindowEvent::CursorLeft { .. } if self.config.mouse_keypad => {
+                // Don't leave the button stuck if the cursor was dragged out of the window
+                self.handle_mouse(false);
+            }
+            WindowEvent::Focused(focused) => {
+                self.handle_focus(focused);
+                if !focused {
+                    self.release_held_btns();
+                }
+
+                // Don't wait for the next frame to stop the biiip
+                if !focused && self.config.mute_on_unfocus {
+                    self.buzzer_set_playing(false);
+                }
             }
             WindowEvent::Resized(size) => {
                 // Window resized
@@ -285,15 +1691,23 @@ impl<'win> ApplicationHandler for App<'win> {
             }
             WindowEvent::CloseRequested => {
                 // Window closed
-                event_loop.exit();
+                self.exit(event_loop);
             }
             _ => ()
         }
     }
     fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        // Poll the game file for modifications
+        if self.config.watch && self.last_watch_time.elapsed() >= Duration::from_millis(WATCH_DELAY) {
+            self.last_watch_time = Instant::now();
+            self.check_game_modified();
+        }
+
         if matches!(cause, StartCause::ResumeTimeReached { .. }) {
             // Request a redraw after delay
-            self.win.as_ref().unwrap().request_redraw();
+            if let Some(win) = &self.win {
+                win.request_redraw();
+            }
         }
     }
 }
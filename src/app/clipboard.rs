@@ -0,0 +1,50 @@
+//! Copying the images to the system clipboard with the clipboard tools of the platform,
+//! so the app doesn't have to speak every clipboard protocol itself
+
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// Command that reads a PNG image from stdin and puts it on the clipboard
+fn png_command() -> Option<Command> {
+    // `pbcopy` and `clip` can't copy images
+    if cfg!(any(target_os = "macos", windows)) {
+        return None;
+    }
+
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut cmd = Command::new("wl-copy");
+        cmd.args(["--type", "image/png"]);
+        Some(cmd)
+    } else if env::var_os("DISPLAY").is_some() {
+        let mut cmd = Command::new("xclip");
+        cmd.args(["-selection", "clipboard", "-target", "image/png", "-in"]);
+        Some(cmd)
+    } else {
+        None
+    }
+}
+
+/// Put the `png` image on the clipboard
+/// Fails if there is no clipboard tool for the platform or it has failed
+pub fn copy_png(png: &[u8]) -> io::Result<()> {
+    let mut cmd = png_command()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no clipboard tool for this platform"))?;
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Stdin is closed right after the image, so the tool knows it has got everything
+    child.stdin.take().unwrap().write_all(png)?;
+
+    // Both tools stay in the background to own the clipboard, so this doesn't block for long
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("clipboard tool has failed ({status})")));
+    }
+    Ok(())
+}
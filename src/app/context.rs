@@ -1,27 +1,58 @@
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc, time::{Duration, Instant}};
 
 use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::cpu::{DISPLAY_DATA_LEN, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use super::renderer::{self, Renderer};
+use crate::{config::{Backend, Color}, cpu::Rect};
 
-/// Number of bytes in the render buffer
-const RENDER_BUF_SIZE: usize = DISPLAY_DATA_LEN * 4;
 const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 
 const UPSCALE_SHADER: &str = include_str!("../../shaders/upscale.wgsl");
+/// Size of the `Grid` struct of the upscale shader
+const GRID_UNIFORM_SIZE: u64 = 16;
+/// How many renders are measured before the render time is logged (see [RenderStats])
+const RENDER_STATS_RENDERS: u32 = 300;
+
+// Errors
+#[derive(Debug)]
+pub enum ContextError {
+    Surface(wgpu::CreateSurfaceError),
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    UnsupportedSurface,
+}
+impl Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Surface(e) => write!(f, "Unable to create the window surface: {e}"),
+            Self::NoAdapter => write!(f, "No compatible GPU found, not even a software one"),
+            Self::Device(e) => write!(f, "Unable to open the GPU: {e}"),
+            Self::UnsupportedSurface => write!(f, "The GPU can't draw in the window"),
+        }
+    }
+}
 
 async fn request_adapter_and_device<'a>(
     instance: &wgpu::Instance,
     surface: &wgpu::Surface<'a>,
-) -> (wgpu::Adapter, wgpu::Device, wgpu::Queue) {
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptionsBase {
-            power_preference: wgpu::PowerPreference::LowPower,
-            force_fallback_adapter: false,
-            compatible_surface: Some(surface),
-        })
-        .await
-        .unwrap();
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), ContextError> {
+    let options = |force_fallback_adapter| wgpu::RequestAdapterOptionsBase {
+        power_preference: wgpu::PowerPreference::LowPower,
+        force_fallback_adapter,
+        compatible_surface: Some(surface),
+    };
+
+    // Software adapter is slow, but better than nothing
+    let adapter = match instance.request_adapter(&options(false)).await {
+        Some(adapter) => adapter,
+        None => {
+            crate::debug!("No GPU adapter, trying the fallback one");
+            instance.request_adapter(&options(true)).await.ok_or(ContextError::NoAdapter)?
+        }
+    };
+
+    let info = adapter.get_info();
+    crate::debug!("Selected the adapter: {} ({:?})", info.name, info.backend);
 
     let (device, queue) = adapter
         .request_device(&wgpu::DeviceDescriptor {
@@ -32,9 +63,67 @@ async fn request_adapter_and_device<'a>(
             memory_hints: wgpu::MemoryHints::MemoryUsage
         }, None)
         .await
-        .unwrap();
+        .map_err(ContextError::Device)?;
 
-    (adapter, device, queue)
+    Ok((adapter, device, queue))
+}
+
+/// Create the surface of the window and open a GPU that can draw on it
+fn open_gpu(
+    win: Arc<Window>,
+    backends: wgpu::Backends,
+) -> Result<(wgpu::Surface<'static>, wgpu::Adapter, wgpu::Device, wgpu::Queue), ContextError> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    let surface = instance.create_surface(win).map_err(ContextError::Surface)?;
+
+    // Request an adapter and a device and block the thread utill we receive them
+    let (adapter, device, queue) = pollster::block_on(request_adapter_and_device(&instance, &surface))?;
+    Ok((surface, adapter, device, queue))
+}
+
+fn backends(backend: Backend) -> wgpu::Backends {
+    match backend {
+        Backend::Vulkan => wgpu::Backends::VULKAN,
+        Backend::Metal => wgpu::Backends::METAL,
+        Backend::Dx12 => wgpu::Backends::DX12,
+        Backend::Gl => wgpu::Backends::GL,
+    }
+}
+
+/// `Fifo` is always supported, the others fall back to it if they aren't
+fn present_mode(vsync: bool) -> wgpu::PresentMode {
+    if vsync { wgpu::PresentMode::AutoVsync }
+    else { wgpu::PresentMode::AutoNoVsync }
+}
+
+/// Wall-clock time spent on submitting and presenting the frames, logged with `--verbose`
+/// The GPU time isn't measured, but a slow GPU stalls the presentation, so it shows up here too
+/// With vsync it also includes waiting for the monitor
+#[derive(Default)]
+struct RenderStats {
+    renders: u32,
+    total: Duration,
+    max: Duration,
+}
+impl RenderStats {
+    fn add(&mut self, time: Duration) {
+        self.renders += 1;
+        self.total += time;
+        self.max = self.max.max(time);
+
+        if self.renders >= RENDER_STATS_RENDERS {
+            crate::debug!(
+                "Render time: {:.2} ms average, {:.2} ms max ({} renders)",
+                self.total.as_secs_f64() * 1000.0 / self.renders as f64,
+                self.max.as_secs_f64() * 1000.0,
+                self.renders,
+            );
+            *self = Self::default();
+        }
+    }
 }
 
 /// Rendering context
@@ -44,28 +133,58 @@ pub struct Context<'win> {
     pub queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
 
-    pub buffer_data: [u8; RENDER_BUF_SIZE],
+    /// RGBA pixels of the render texture
+    buffer_data: Vec<u8>,
+    /// Width of the render texture, the displays are placed side by side
+    width: u32,
     render_texture: wgpu::Texture,
 
+    /// Color and opacity of the pixel grid (see [Renderer::set_grid])
+    grid_buffer: wgpu::Buffer,
+    /// Color of the window where the displays don't cover it (see [Renderer::set_clear_color])
+    clear_color: wgpu::Color,
+    stats: RenderStats,
+
     upscale_pipeline: wgpu::RenderPipeline,
     upscale_bind_group: wgpu::BindGroup
 }
 impl<'win> Context<'win> {
-    pub fn new(win: Arc<Window>) -> Self {
+    /// With `vsync` the frames are presented in sync with the monitor refresh rate
+    /// Render texture is `width` x `height` pixels, it fits all the displays side by side
+    /// (see [super::Compare])
+    /// With `smooth` the pixels are upscaled with the linear filtering instead of staying crisp
+    /// Unavailable `backend` falls back to the automatically chosen one
+    pub fn new(
+        win: Arc<Window>,
+        vsync: bool,
+        backend: Option<Backend>,
+        smooth: bool,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ContextError> {
         let win_size = win.inner_size();
 
-        let instance = wgpu::Instance::default();
-        let surface = instance.create_surface(win).unwrap();
-
-        // Request an adapter and a device and block the thread utill we receive them
-        let (adapter, device, queue) = pollster::block_on(request_adapter_and_device(&instance, &surface));
+        let gpu = match backend {
+            Some(backend) => match open_gpu(Arc::clone(&win), backends(backend)) {
+                Err(ContextError::NoAdapter) => {
+                    eprintln!("Backend {} is not available, using the default one", backend.name());
+                    None
+                }
+                res => Some(res?),
+            },
+            None => None,
+        };
+        let (surface, adapter, device, queue) = match gpu {
+            Some(gpu) => gpu,
+            None => open_gpu(win, wgpu::InstanceDescriptor::default().backends)?,
+        };
 
         // Create render target texture
         let render_texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Render target texture"),
             size: wgpu::Extent3d {
-                width: DISPLAY_WIDTH,
-                height: DISPLAY_HEIGHT,
+                width,
+                height,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -80,13 +199,24 @@ impl<'win> Context<'win> {
             format: Some(TEXTURE_FORMAT),
             ..Default::default()
         });
+        let filter =
+            if smooth { wgpu::FilterMode::Linear }
+            else { wgpu::FilterMode::Nearest };
         let render_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("Render target sampler"),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
             ..Default::default()
         });
 
+        // Grid is disabled until it is set
+        let grid_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grid uniform buffer"),
+            size: GRID_UNIFORM_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         // Create upscale bind group
         let upscale_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Upscale bind group layout"),
@@ -95,7 +225,7 @@ impl<'win> Context<'win> {
                     binding: 0,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        sample_type: wgpu::TextureSampleType::Float { filterable: smooth },
                         view_dimension: wgpu::TextureViewDimension::D2,
                         multisampled: false,
                     },
@@ -104,7 +234,20 @@ impl<'win> Context<'win> {
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    ty: wgpu::BindingType::Sampler(
+                        if smooth { wgpu::SamplerBindingType::Filtering }
+                        else { wgpu::SamplerBindingType::NonFiltering }
+                    ),
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
                     count: None
                 }
             ],
@@ -121,6 +264,10 @@ impl<'win> Context<'win> {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&render_sampler)
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: grid_buffer.as_entire_binding()
+                },
             ],
         });
 
@@ -162,25 +309,33 @@ impl<'win> Context<'win> {
         // Create surface config
         let mut config = surface
             .get_default_config(&adapter, win_size.width, win_size.height)
-            .unwrap();
+            .ok_or(ContextError::UnsupportedSurface)?;
         config.format = TEXTURE_FORMAT;
+        config.present_mode = present_mode(vsync);
         surface.configure(&device, &config);
 
-        Self {
+        Ok(Self {
             surface,
             device,
             queue,
             config,
 
-            buffer_data: [0; RENDER_BUF_SIZE],
+            buffer_data: vec![0; (width * height * 4) as usize],
+            width,
             render_texture,
 
+            grid_buffer,
+            clear_color: wgpu::Color::BLACK,
+            stats: RenderStats::default(),
+
             upscale_pipeline,
             upscale_bind_group,
-        }
+        })
     }
-
-    pub fn render(&mut self) {
+}
+impl<'win> Renderer for Context<'win> {
+    fn render(&mut self) {
+        let start = Instant::now();
         let frame = self.surface.get_current_texture().unwrap();
         let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("Upscale frame view"),
@@ -195,7 +350,7 @@ impl<'win> Context<'win> {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -211,26 +366,64 @@ impl<'win> Context<'win> {
 
         self.queue.submit(Some(encoder.finish()));
         frame.present();
+
+        self.stats.add(start.elapsed());
+    }
+    fn set_vsync(&mut self, vsync: bool) {
+        self.config.present_mode = present_mode(vsync);
+        self.surface.configure(&self.device, &self.config);
     }
-    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
         self.config.width = size.width;
         self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
     }
 
-    /// Write the buffer to the texture
-    pub fn write_buf(&mut self) {
+    fn set_grid(&mut self, grid: Option<(Color, f32)>) {
+        let (color, opacity) = grid.unwrap_or((Color(0, 0, 0), 0.0));
+        let channel = |c: u8| c as f32 / 255.0;
+
+        let uniform = [channel(color.0), channel(color.1), channel(color.2), opacity];
+        let bytes: Vec<u8> = uniform.iter().flat_map(|f| f.to_le_bytes()).collect();
+        self.queue.write_buffer(&self.grid_buffer, 0, &bytes);
+    }
+
+    fn set_clear_color(&mut self, color: &Color) {
+        let channel = |c: u8| c as f64 / 255.0;
+        self.clear_color = wgpu::Color {
+            r: channel(color.0),
+            g: channel(color.1),
+            b: channel(color.2),
+            a: 1.0,
+        };
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.buffer_data
+    }
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Color) {
+        renderer::set_pixel(&mut self.buffer_data, self.width, x, y, color);
+    }
+    fn write_buf(&mut self, rect: Rect) {
         self.queue.write_texture(
-            self.render_texture.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                origin: wgpu::Origin3d { x: rect.x, y: rect.y, z: 0 },
+                ..self.render_texture.as_image_copy()
+            },
             &self.buffer_data,
             wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(DISPLAY_WIDTH * 4),
-                rows_per_image: Some(DISPLAY_HEIGHT),
+                // Skip everything before the first pixel of the region
+                offset: ((rect.y * self.width + rect.x) * 4) as u64,
+                bytes_per_row: Some(self.width * 4),
+                rows_per_image: Some(rect.h),
             },
             wgpu::Extent3d {
-                width: DISPLAY_WIDTH,
-                height: DISPLAY_HEIGHT,
+                width: rect.w,
+                height: rect.h,
                 depth_or_array_layers: 1,
             },
         )
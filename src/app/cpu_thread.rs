@@ -0,0 +1,189 @@
+//! Running the CPU on its own thread, so the window stays responsive at any speed
+//! (see [crate::config::Config::threaded])
+//!
+//! The thread owns the CPU and sends copies of it back, the app draws the latest copy
+//! and sends the changes it wants to make to the CPU as closures
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cpu::Cpu;
+
+/// How often the copy of the CPU is sent, once in a frame of the app
+const SNAPSHOT_DELAY: Duration = Duration::from_millis(super::TARGET_DELAY);
+/// Sleep between the batches of the cycles, so the thread doesn't take the whole CPU core
+const BATCH_DELAY: Duration = Duration::from_millis(1);
+
+/// Change made to the thread's CPU
+type CpuFn = Box<dyn FnOnce(&mut Cpu) + Send>;
+
+/// Cycles per 1/60 of a second and cycles between the timer updates
+type Speed = (u32, u32);
+
+enum Command {
+    /// `None` pauses the CPU
+    SetSpeed(Option<Speed>),
+    Run(CpuFn),
+}
+
+/// Copy of the CPU with what has happened since the previous snapshot
+pub struct Snapshot {
+    pub cpu: Cpu,
+    /// Whether an instruction has failed, the error is already printed
+    pub failed: bool,
+    pub display_changed: bool,
+    /// Cycles that were executed
+    pub cycles: i64,
+    /// Cycles that should have been executed at the current speed
+    pub budget: i64,
+}
+
+/// What should go into the next snapshot
+#[derive(Default)]
+struct Pending {
+    changed: bool,
+    failed: bool,
+    display_changed: bool,
+    cycles: i64,
+    budget: i64,
+}
+
+pub struct CpuThread {
+    commands: Sender<Command>,
+    snapshots: Receiver<Snapshot>,
+    /// Last speed sent to the thread
+    speed: Option<Speed>,
+}
+impl CpuThread {
+    /// Start the thread with a copy of the `cpu`, it is paused until [CpuThread::set_speed]
+    /// The thread stops when this is dropped
+    pub fn spawn(cpu: Cpu) -> Self {
+        let (commands, commands_rx) = mpsc::channel();
+        // Only the latest copy matters, the app never catches up with the old ones
+        let (snapshots_tx, snapshots) = mpsc::sync_channel(1);
+
+        thread::Builder::new()
+            .name("cpu".into())
+            .spawn(move || run(cpu, commands_rx, snapshots_tx))
+            .unwrap();
+
+        Self { commands, snapshots, speed: None }
+    }
+
+    /// Step the CPU `cycles` times in 1/60 of a second and update the timers every
+    /// `timer_period` cycles, or pause it with `None`
+    pub fn set_speed(&mut self, speed: Option<(u32, u32)>) {
+        if self.speed == speed { return }
+        self.speed = speed;
+        let _ = self.commands.send(Command::SetSpeed(speed));
+    }
+    /// Change the CPU of the thread, the change shows up in the next snapshot
+    pub fn run(&self, f: impl FnOnce(&mut Cpu) + Send + 'static) {
+        let _ = self.commands.send(Command::Run(Box::new(f)));
+    }
+    /// Snapshot sent since the previous call, if any
+    pub fn try_snapshot(&self) -> Option<Snapshot> {
+        self.snapshots.try_recv().ok()
+    }
+}
+
+fn run(mut cpu: Cpu, commands: Receiver<Command>, snapshots: SyncSender<Snapshot>) {
+    let mut speed: Option<Speed> = None;
+    let mut last_step = Instant::now();
+    let mut last_snapshot = Instant::now();
+    // Fraction of a cycle left from the previous batch
+    let mut carry = 0.0f64;
+    let mut timer_cycles = 0u32;
+    let mut pending = Pending::default();
+
+    loop {
+        // Nothing to do but wait for the commands while paused
+        let mut next = None;
+        if speed.is_none() && !pending.changed {
+            match commands.recv() {
+                Ok(command) => next = Some(command),
+                Err(_) => return,
+            }
+        }
+
+        loop {
+            let command = match next.take().map(Ok).unwrap_or_else(|| commands.try_recv()) {
+                Ok(command) => command,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            };
+
+            match command {
+                Command::SetSpeed(new) => {
+                    // Paused time isn't caught up with
+                    if speed.is_none() {
+                        last_step = Instant::now();
+                    }
+                    speed = new;
+                }
+                Command::Run(f) => {
+                    f(&mut cpu);
+                    pending.changed = true;
+                }
+            }
+        }
+
+        if let Some((cycles, timer_period)) = speed {
+            let now = Instant::now();
+            let due = now.duration_since(last_step).as_secs_f64() * 60.0 * cycles as f64;
+            last_step = now;
+            pending.budget += due as i64;
+
+            // At most a frame of the cycles is carried, the game just runs slower if the CPU can't keep up
+            carry = (carry + due).min(cycles as f64);
+            while carry >= 1.0 {
+                carry -= 1.0;
+
+                match cpu.step() {
+                    Ok(res) => pending.display_changed |= res.display_changed,
+                    Err(e) => {
+                        eprintln!("Execution error: {}", e);
+                        pending.failed = true;
+                    }
+                }
+                pending.cycles += 1;
+                pending.changed = true;
+
+                timer_cycles += 1;
+                if timer_cycles >= timer_period {
+                    timer_cycles = 0;
+                    cpu.step_timers();
+                }
+            }
+        }
+
+        // Changes made during the pause are sent right away
+        if pending.changed && (speed.is_none() || last_snapshot.elapsed() >= SNAPSHOT_DELAY) {
+            let snapshot = Snapshot {
+                cpu: cpu.clone(),
+                failed: pending.failed,
+                display_changed: pending.display_changed,
+                cycles: pending.cycles,
+                budget: pending.budget,
+            };
+
+            match snapshots.try_send(snapshot) {
+                Ok(()) => {
+                    // The app has got them with the snapshot
+                    cpu.take_dirty();
+                    cpu.take_last_draw();
+                    pending = Pending::default();
+                    last_snapshot = Instant::now();
+                }
+                // The app hasn't taken the previous one yet
+                Err(TrySendError::Full(_)) => (),
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+
+        thread::sleep(BATCH_DELAY);
+    }
+}
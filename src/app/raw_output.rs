@@ -0,0 +1,60 @@
+//! Streaming the rendered frames as raw pixels, for example into ffmpeg:
+//! `pitch1002 game.ch8 --raw-output - | ffmpeg -f rawvideo -pixel_format rgba -video_size 64x32 -framerate 60 -i - out.mp4`
+//!
+//! There is no header, every frame is just `width * height` RGBA pixels, row by row
+//! Size of the frames never changes and is printed when the output is opened
+
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the frames into stdout or a file (works with named pipes too)
+pub struct RawOutput {
+    out: BufWriter<Box<dyn Write>>,
+    /// Every pixel is written as `scale` x `scale` pixels
+    scale: u32,
+    /// Single scaled row, so it is not allocated for every frame
+    row: Vec<u8>,
+}
+impl RawOutput {
+    /// `path` "-" is stdout
+    pub fn open(path: &Path, scale: u32) -> io::Result<Self> {
+        let out: Box<dyn Write> =
+            if path == Path::new("-") { Box::new(io::stdout()) }
+            else { Box::new(OpenOptions::new().write(true).create(true).truncate(true).open(path)?) };
+
+        Ok(Self {
+            out: BufWriter::new(out),
+            scale,
+            row: Vec::new(),
+        })
+    }
+
+    /// Size of the written frames for the frames of the given size
+    pub fn frame_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width * self.scale, height * self.scale)
+    }
+
+    /// Write the RGBA `pixels` of a `width` pixels wide frame
+    pub fn write_frame(&mut self, pixels: &[u8], width: u32) -> io::Result<()> {
+        let scale = self.scale as usize;
+
+        for row in pixels.chunks_exact(width as usize * 4) {
+            self.row.clear();
+            for pixel in row.chunks_exact(4) {
+                for _ in 0..scale {
+                    self.row.extend_from_slice(pixel);
+                }
+            }
+
+            for _ in 0..scale {
+                self.out.write_all(&self.row)?;
+            }
+        }
+
+        // The consumer should get the frame right away
+        self.out.flush()
+    }
+}
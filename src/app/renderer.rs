@@ -0,0 +1,39 @@
+use winit::dpi::PhysicalSize;
+
+use crate::{config::Color, cpu::Rect};
+
+/// Shows the render buffer, the displays are placed side by side in it (see [super::Compare])
+/// Implemented by [super::Context] (wgpu) and [super::SoftwareRenderer] (CPU, into an image)
+pub trait Renderer {
+    /// Show the written regions of the buffer
+    fn render(&mut self);
+    fn set_vsync(&mut self, vsync: bool);
+    fn resize(&mut self, size: PhysicalSize<u32>);
+
+    /// Draw the lines between the CHIP-8 pixels with the `color` and `opacity` (from 0.0 to 1.0),
+    /// `None` hides them
+    fn set_grid(&mut self, grid: Option<(Color, f32)>);
+    /// Window is filled with the `color` before the displays are drawn on top of it
+    fn set_clear_color(&mut self, color: &Color);
+
+    /// RGBA pixels of the whole render buffer, row by row
+    fn buffer(&self) -> &[u8];
+    /// Width of the render buffer
+    fn width(&self) -> u32;
+
+    /// Set the color of the pixel in the buffer
+    /// Call [Renderer::write_buf] to send it to the screen
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Color);
+    /// Write the `rect` region of the buffer to the screen, it is shown on the next render
+    fn write_buf(&mut self, rect: Rect);
+}
+
+/// Set the color of the pixel in the RGBA `buffer` that is `width` pixels wide
+pub fn set_pixel(buffer: &mut [u8], width: u32, x: u32, y: u32, color: &Color) {
+    let i = ((y * width + x) * 4) as usize;
+
+    buffer[i + 0] = color.0; // Red
+    buffer[i + 1] = color.1; // Green
+    buffer[i + 2] = color.2; // Blue
+    buffer[i + 3] = 255; // Alpha
+}
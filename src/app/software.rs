@@ -0,0 +1,158 @@
+//! Rendering on the CPU, without wgpu (see `--software`)
+//!
+//! Nothing can be drawn in the window without a GPU, so the upscaled frames are saved into
+//! a PNG file instead, it is rewritten every time the screen changes
+//! The window is still opened, it takes the input
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use winit::dpi::PhysicalSize;
+
+use super::renderer::{self, Renderer};
+use crate::{config::Color, cpu::Rect, png};
+
+/// Every pixel of the `width` pixels wide RGBA `pixels` becomes `scale` x `scale` pixels,
+/// the `grid` lines are one pixel thick at the top and left edges of each of them
+/// (same as the upscale shader of [super::Context])
+pub fn upscale(pixels: &[u8], width: u32, scale: u32, grid: Option<&(Color, f32)>) -> Vec<u8> {
+    let scale = scale as usize;
+    let mut out = Vec::with_capacity(pixels.len() * scale * scale);
+    let mut row = Vec::with_capacity(width as usize * scale * 4);
+    let mut line = Vec::with_capacity(row.capacity());
+
+    for src in pixels.chunks_exact(width as usize * 4) {
+        row.clear();
+        line.clear();
+        for pixel in src.chunks_exact(4) {
+            let color = Color(pixel[0], pixel[1], pixel[2]);
+            let on_line = match grid {
+                Some((grid, opacity)) => color.mix(grid, *opacity),
+                None => color.clone(),
+            };
+
+            for i in 0..scale {
+                let left = if i == 0 { &on_line } else { &color };
+                row.extend_from_slice(&[left.0, left.1, left.2, pixel[3]]);
+                line.extend_from_slice(&[on_line.0, on_line.1, on_line.2, pixel[3]]);
+            }
+        }
+
+        out.extend_from_slice(&line);
+        for _ in 1..scale {
+            out.extend_from_slice(&row);
+        }
+    }
+
+    out
+}
+
+/// Write the whole file next to the `path` first, so the image is never seen half-written
+fn write_image(path: &Path, png: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, png)?;
+    fs::rename(&tmp, path)
+}
+
+/// Renders the frames into the PNG file on the CPU
+pub struct SoftwareRenderer {
+    /// `None` after the image couldn't be written
+    path: Option<PathBuf>,
+    /// RGBA pixels of the render buffer
+    buffer_data: Vec<u8>,
+    /// Width of the render buffer, the displays are placed side by side
+    width: u32,
+    /// Every pixel is saved as `scale` x `scale` pixels
+    scale: u32,
+    grid: Option<(Color, f32)>,
+    /// Whether the buffer was written since the image was saved
+    changed: bool,
+}
+impl SoftwareRenderer {
+    /// Render buffer is `width` x `height` pixels, same as the render texture of [super::Context]
+    pub fn new(path: PathBuf, scale: u32, width: u32, height: u32) -> Self {
+        Self {
+            path: Some(path),
+            buffer_data: vec![0; (width * height * 4) as usize],
+            width,
+            scale,
+            grid: None,
+            changed: true,
+        }
+    }
+}
+impl Renderer for SoftwareRenderer {
+    fn render(&mut self) {
+        let Some(path) = &self.path else { return };
+        if !self.changed { return }
+        self.changed = false;
+
+        let image = upscale(&self.buffer_data, self.width, self.scale, self.grid.as_ref());
+        let height = self.buffer_data.len() as u32 / 4 / self.width;
+        let png = png::encode(self.width * self.scale, height * self.scale, &image);
+
+        if let Err(e) = write_image(path, &png) {
+            eprintln!("Unable to write the frame '{}': {}", path.display(), e);
+            eprintln!("Software rendering stopped");
+            self.path = None;
+        }
+    }
+    // Frames are saved only when they change, there is nothing to sync
+    fn set_vsync(&mut self, _vsync: bool) {}
+    // Size of the image doesn't depend on the window
+    fn resize(&mut self, _size: PhysicalSize<u32>) {}
+
+    fn set_grid(&mut self, grid: Option<(Color, f32)>) {
+        self.grid = grid;
+        self.changed = true;
+    }
+    // Displays cover the whole image
+    fn set_clear_color(&mut self, _color: &Color) {}
+
+    fn buffer(&self) -> &[u8] {
+        &self.buffer_data
+    }
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: &Color) {
+        renderer::set_pixel(&mut self.buffer_data, self.width, x, y, color);
+    }
+    fn write_buf(&mut self, _rect: Rect) {
+        self.changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upscale_repeats_pixels() {
+        let pixels = [1, 2, 3, 255, 4, 5, 6, 255];
+        let out = upscale(&pixels, 2, 2, None);
+
+        let row = [1, 2, 3, 255, 1, 2, 3, 255, 4, 5, 6, 255, 4, 5, 6, 255];
+        assert_eq!(out, [row, row].concat());
+    }
+
+    #[test]
+    fn upscale_draws_grid_on_top_and_left_edges() {
+        let white = Color(255, 255, 255);
+        let pixels = [255, 255, 255, 255];
+        let out = upscale(&pixels, 1, 3, Some(&(Color(0, 0, 0), 1.0)));
+
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 3 + x) * 4;
+            Color(out[i], out[i + 1], out[i + 2])
+        };
+        for i in 0..3 {
+            assert_eq!(pixel(i, 0), Color(0, 0, 0));
+            assert_eq!(pixel(0, i), Color(0, 0, 0));
+        }
+        assert_eq!(pixel(1, 1), white);
+        assert_eq!(pixel(2, 2), white);
+    }
+}
@@ -1,57 +1,150 @@
-use crate::{cpu::{DISPLAY_DATA_LEN, DISPLAY_HEIGHT, DISPLAY_WIDTH}, font::UI_FONT};
+use crate::{cpu::Rect, font::UI_FONT};
 
 use super::App;
 
+/// How many frames the message box stays on the screen
+const MSG_DURATION: u8 = 30;
+
 /// Ui
 pub struct Ui {
-    pub speed_msg_timer: u8,
+    /// Text of the message box at the top of the screen
+    msg: String,
+    msg_timer: u8,
+    /// How many frames the splash screen stays on the screen
+    splash_timer: u16,
+    /// Size of a UI font pixel in display pixels (see [crate::config::Config::ui_scale])
+    scale: u8,
 }
 impl Ui {
-    pub fn new() -> Self {
+    pub fn new(splash_frames: u16, scale: u8) -> Self {
         Self {
-            speed_msg_timer: 0
+            msg: String::new(),
+            msg_timer: 0,
+            splash_timer: splash_frames,
+            scale,
         }
     }
+
+    /// Whether the splash screen is shown, the game doesn't run meanwhile
+    pub fn is_splash(&self) -> bool {
+        self.splash_timer > 0
+    }
+    pub fn step_splash(&mut self) {
+        self.splash_timer = self.splash_timer.saturating_sub(1);
+    }
+    pub fn skip_splash(&mut self) {
+        self.splash_timer = 0;
+    }
+
+    /// Show a short message at the top of the screen
+    pub fn show_msg(&mut self, msg: impl Into<String>) {
+        self.msg = msg.into();
+        self.msg_timer = MSG_DURATION;
+    }
+}
+
+/// Real time of the `frames` at 60 Hz, like "1:05.50"
+fn format_frames(frames: u64) -> String {
+    let secs = frames / 60;
+    let centis = frames % 60 * 100 / 60;
+    format!("{}:{:02}.{:02}", secs / 60, secs % 60, centis)
 }
 
 impl<'win> App<'win> {
     pub fn draw_ui(&mut self) {
-        let dw = DISPLAY_WIDTH as u8;
-        let dh = DISPLAY_HEIGHT as u8;
+        // UI is placed on the current display, it always fits in a byte (see [crate::cpu::MAX_DISPLAY_SIZE])
+        let dw = self.cpu.width() as u8;
+        let dh = self.cpu.height() as u8;
+        let scale = self.ui.scale;
+        // Message boxes fit a line of text with 1 pixel padding
+        let box_h = 5 * scale + 2;
 
         // Clear ui screen
         self.screen.fill(None);
+        let last_bounds = self.ui_bounds.take();
+
+        // Draw CPU load meter at the very top, below everything
+        if self.config.cpu_meter {
+            let load = self.frame_cycles as f32 / self.frame_budget.max(1) as f32;
+            let w = (dw as f32 * load.min(1.0)).round() as u8;
+            // UI has only two colors, so the bar blinks while the CPU can't keep up
+            let on = !self.throttled || self.frame % 16 < 8;
+
+            self.draw_rect(0, 0, w, 1, on);
+            self.draw_rect(w, 0, dw - w, 1, !on);
+        }
+
+        // Draw speedrun timer below the meter, messages cover it for a moment
+        if self.config.timer {
+            let time = format_frames(self.run_frames);
+            let frames = self.run_frames.to_string();
+            let w = time.len().max(frames.len()) as u8 * 5 * scale + 1;
+            let h = 2 * (5 * scale + 1) + 1;
+            let y = u8::from(self.config.cpu_meter);
 
-        // Draw speed message box
-        if self.ui.speed_msg_timer > 0 {
+            self.draw_rect(0, y, w.min(dw), h, true);
+            self.draw_text(&time, 1, y + 1, scale, false);
+            self.draw_text(&frames, 1, y + 5 * scale + 2, scale, false);
+        }
+
+        // Draw message box
+        if self.ui.msg_timer > 0 {
             let w = dw;
+            let msg = self.ui.msg.clone();
 
-            self.draw_rect(0, 0, w, 7, true);
-            self.draw_rect(0, 7, w, 1, false);
-            self.draw_text(&format!("speed {}", self.config.speed), 1, 1, false);
+            self.draw_rect(0, 0, w, box_h, true);
+            self.draw_rect(0, box_h, w, 1, false);
+            self.draw_text(&msg, 1, 1, scale, false);
 
-            self.ui.speed_msg_timer -= 1;
+            self.ui.msg_timer -= 1;
+        }
+
+        // Draw no game message box, there is nothing to run
+        if !self.cpu.is_ready() {
+            let y = dh.saturating_sub(box_h);
+
+            self.draw_rect(0, y.saturating_sub(1), dw, 1, false);
+            self.draw_rect(0, y, dw, box_h, true);
+            self.draw_text("no game", 1, y + 1, scale, false);
+        }
+
+        // Draw program end message box
+        if self.cpu.is_halted() && !self.is_paused {
+            let w = dw;
+            let h = box_h;
+            let y = dh.saturating_sub(h);
+            // The full text is exactly as wide as the screen without scaling
+            let text = if scale == 1 { "program ended" } else { "ended" };
+
+            self.draw_rect(0, y.saturating_sub(1), w, 1, false);
+            self.draw_rect(0, y, w, h, true);
+            self.draw_text(text, 0, y + 1, scale, false);
         }
 
         // Draw pause message box
         if self.is_paused {
             let w = dw;
-            let h = 7;
+            let h = box_h;
             let x = 0;
-            let y = dh - h;
+            let y = dh.saturating_sub(h);
+
+            self.draw_rect(x, y.saturating_sub(1), w, 1, false);
+            // Timer ticks made by hand are counted too
+            let text =
+                if self.paused_ticks > 0 { format!("paused t{}", self.paused_ticks) }
+                else { String::from("paused") };
 
-            self.draw_rect(x, y-1, w, 1, false);
             self.draw_rect(x, y, w, h, true);
-            self.draw_text("paused", x + 1, y + 1, false);
+            self.draw_text(&text, x + 1, y + 1, scale, false);
         }
 
         // Draw fast forward message box
         if self.is_fastforward {
-            let w = 7;
-            let h = 5;
-            let x = DISPLAY_WIDTH as u8 - w - 1;
-            let y = DISPLAY_HEIGHT as u8 - h - 1;
-            self.draw_rect(x - 1, y - 1, w + 2, h + 2, false);
+            let w = 5 * scale + 2;
+            let h = 3 * scale + 2;
+            let x = dw.saturating_sub(w + 1);
+            let y = dh.saturating_sub(h + 1);
+            self.draw_rect(x.saturating_sub(1), y.saturating_sub(1), w + 2, h + 2, false);
             self.draw_rect(x, y, w, h, true);
             // >>
             self.draw_sprite(
@@ -62,9 +155,27 @@ impl<'win> App<'win> {
                 ],
                 x + 1,
                 y + 1,
+                scale,
                 false,
             )
         }
+
+        // Draw splash screen above everything
+        if self.ui.is_splash() {
+            let text = "pitch1002";
+            // Each char is 5 pixels wide including the spacing
+            // The logo is never scaled, it wouldn't fit
+            // Too small displays show only the beginning of the logo
+            let x = dw.saturating_sub(text.len() as u8 * 5) / 2;
+            let y = dh.saturating_sub(5) / 2;
+
+            self.draw_rect(0, 0, dw, dh, false);
+            self.draw_text(text, x, y, 1, true);
+        }
+
+        // Both the old and the new UI need to be rerendered
+        let bounds = Rect::merge(last_bounds, self.ui_bounds);
+        self.ui_dirty = Rect::merge(self.ui_dirty, bounds);
     }
 
     /// Draw a filled rect on the screen
@@ -73,19 +184,30 @@ impl<'win> App<'win> {
         let y = y as usize;
         let w = w as usize;
         let h = h as usize;
-        let sw = DISPLAY_WIDTH as usize;
+        let sw = self.cpu.width() as usize;
+        let sh = self.cpu.height() as usize;
+        if x >= sw { return }
 
-        for row in 0..h {
-            let line = y + row;
+        for line in y..(y + h).min(sh) {
             let start = line * sw + x;
-            let end = (line * sw + x + w).min(DISPLAY_DATA_LEN);
+            let end = line * sw + (x + w).min(sw);
 
             self.screen[start..end].fill(Some(on));
         }
+
+        if x < sw && y < sh && w > 0 && h > 0 {
+            let rect = Rect {
+                x: x as u32,
+                y: y as u32,
+                w: w.min(sw - x) as u32,
+                h: h.min(sh - y) as u32,
+            };
+            self.ui_bounds = Rect::merge(self.ui_bounds, Some(rect));
+        }
     }
-    /// Draw a text on the screen
+    /// Draw a text on the screen, each font pixel is `scale` x `scale` pixels
     /// Be a good boy, and use only lowercase characters
-    fn draw_text(&mut self, text: &str, x: u8, y: u8, on: bool) {
+    fn draw_text(&mut self, text: &str, x: u8, y: u8, scale: u8, on: bool) {
         for (char_idx, chr) in text.chars().enumerate() {
             if chr == ' ' { continue }
 
@@ -103,6 +225,7 @@ impl<'win> App<'win> {
                 else if ascii == 124 { 43 } // |
                 else if ascii == 92 { 44 } // \
                 else if ascii == 45 { 45 } // -
+                else if ascii == 58 { 46 } // :
                 else { 0 }; // Everything else
 
             let font_row = font_idx as usize * 5;
@@ -110,31 +233,45 @@ impl<'win> App<'win> {
                 return;
             };
 
+            let char_x = x as usize + char_idx * 5 * scale as usize;
+            // Text that doesn't fit is cut off
+            if char_x >= self.cpu.width() as usize { return }
+
             self.draw_sprite(
                 sprite,
-                x + char_idx as u8 * 5,
+                char_x as u8,
                 y,
+                scale,
                 on,
             );
         }
     }
-    fn draw_sprite(&mut self, rows: &[u8], x: u8, y: u8, on: bool) {
+    /// Draw a sprite on the screen, each sprite pixel is `scale` x `scale` pixels
+    /// Pixels outside of the screen are not drawn
+    fn draw_sprite(&mut self, rows: &[u8], x: u8, y: u8, scale: u8, on: bool) {
         let x = x as usize;
         let y = y as usize;
-        let sw = DISPLAY_WIDTH as usize;
-        let sh = DISPLAY_HEIGHT as usize;
+        let scale = scale as usize;
+        let sw = self.cpu.width() as usize;
+        let sh = self.cpu.height() as usize;
 
         for row in 0..rows.len() {
             let mut pixels = rows[row];
 
             for col in 0..8 {
                 if pixels & 0x80 != 0 {
-                    let cx = (x + col) % sw;
-                    let cy = (y + row) % sh;
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            let cx = x + col * scale + sx;
+                            let cy = y + row * scale + sy;
+                            if cx >= sw || cy >= sh { continue }
 
-                    let idx = cy * sw + cx;
+                            let idx = cy * sw + cx;
 
-                    self.screen[idx as usize] = Some(on);
+                            self.screen[idx] = Some(on);
+                            self.ui_bounds = Rect::merge(self.ui_bounds, Some(Rect::pixel(cx as u32, cy as u32)));
+                        }
+                    }
                 }
 
                 pixels <<= 1;
@@ -142,3 +279,85 @@ impl<'win> App<'win> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        cli::Cli,
+        config::{Config, EnvConfig, RomConfig, SavedState, MAX_UI_SCALE},
+        cpu::Cpu,
+    };
+
+    use super::App;
+
+    /// App running a game that ends on the first step, on a `width` x `height` display
+    fn app_with_display(width: u32, height: u32, ui_scale: u8) -> App<'static> {
+        let cli = Cli { ui_scale: Some(ui_scale), timer: true, cpu_meter: true, ..Cli::default() };
+        let config = Config::new(cli, EnvConfig::default(), RomConfig::default(), SavedState::default()).unwrap();
+
+        let mut cpu = Cpu::with_display(width, height);
+        // 1200 jumps to itself
+        cpu.load(&[0x12, 0x00]);
+
+        App::new(config, cpu, None, None, PathBuf::from("game.ch8"), None, None)
+    }
+
+    /// Whether the UI pixel is on, off or not drawn at all
+    fn pixel(app: &App, x: u32, y: u32) -> Option<bool> {
+        app.screen[(y * app.cpu.width() + x) as usize]
+    }
+    /// Whether the whole `y` row is the UI `on` color
+    fn row_is(app: &App, y: u32, on: bool) -> bool {
+        (0..app.cpu.width()).all(|x| pixel(app, x, y) == Some(on))
+    }
+
+    // Pixels are clipped to the display, so the boxes are checked to land right at its edges
+    #[test]
+    fn ui_fits_the_display() {
+        // Low and high resolution
+        for (width, height) in [(64, 32), (128, 64)] {
+            for scale in 1..=MAX_UI_SCALE {
+                let mut app = app_with_display(width, height, scale);
+                assert_eq!(app.screen.len(), (width * height) as usize);
+                let box_h = 5 * scale as u32 + 2;
+
+                // Splash screen covers the whole display
+                app.draw_ui();
+                assert!(app.screen.iter().all(Option::is_some), "{}x{} splash", width, height);
+
+                // Message box is at the top and as wide as the display
+                app.ui.skip_splash();
+                app.ui.show_msg("a very long message, longer than any display");
+                app.draw_ui();
+                assert!(row_is(&app, 0, true), "{}x{} message", width, height);
+                assert!(row_is(&app, box_h, false), "{}x{} message", width, height);
+
+                // Pause box is at the bottom
+                app.is_paused = true;
+                app.paused_ticks = 1000;
+                app.draw_ui();
+                assert!(row_is(&app, height - 1, true), "{}x{} pause", width, height);
+                assert!(row_is(&app, height - box_h - 1, false), "{}x{} pause", width, height);
+
+                // Fast forward box is in the bottom right corner, with the outline around it
+                app.is_paused = false;
+                app.is_fastforward = true;
+                app.draw_ui();
+                let (w, h) = (5 * scale as u32 + 2, 3 * scale as u32 + 2);
+                assert_eq!(pixel(&app, width - 2, height - 2), Some(true), "{}x{} fast forward", width, height);
+                assert_eq!(pixel(&app, width - w - 1, height - h - 1), Some(true), "{}x{} fast forward", width, height);
+                assert_eq!(pixel(&app, width - 1, height - 1), Some(false), "{}x{} fast forward", width, height);
+                assert_eq!(pixel(&app, width - w - 2, height - h - 2), Some(false), "{}x{} fast forward", width, height);
+
+                // Program end box is at the bottom, same as the pause one
+                app.is_fastforward = false;
+                app.cpu.step().unwrap();
+                app.draw_ui();
+                assert!(row_is(&app, height - 1, true), "{}x{} end", width, height);
+                assert!(row_is(&app, height - box_h - 1, false), "{}x{} end", width, height);
+            }
+        }
+    }
+}
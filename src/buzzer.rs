@@ -1,88 +1,236 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    sync::{atomic::{AtomicBool, AtomicU32, Ordering}, Arc},
+    time::{Duration, Instant},
+};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, SampleFormat};
+
+/// Sample formats the buzzer can play, from the most preferred
+const SUPPORTED_FORMATS: [SampleFormat; 3] = [SampleFormat::F32, SampleFormat::I16, SampleFormat::U16];
 
 // Errors
 #[derive(Debug)]
 pub enum BuzzerError {
     NoOutputDevice,
     NoAvaliableConfigs,
+    UnsupportedFormat,
 }
 impl Display for BuzzerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NoOutputDevice => write!(f, "No output device was found"),
-            Self::NoAvaliableConfigs => write!(f, "Unable to fetch a stream config")
+            Self::NoAvaliableConfigs => write!(f, "Unable to fetch a stream config"),
+            Self::UnsupportedFormat => write!(f, "Output device doesn't support f32, i16 or u16 samples"),
         }
     }
 }
 
+/// Build a stream playing biiip with samples of type `T`
+/// `pitch` is a frequency multiplier stored as [f32] bits,
+/// `failed` is set when the stream stops working (for example, the device was unplugged)
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    pitch: Arc<AtomicU32>,
+    failed: Arc<AtomicBool>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    // Samples of all channels are interleaved in the buffer,
+    // so all samples of a frame must have the same value
+    let channels = config.channels.max(1) as usize;
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let pitch = f32::from_bits(pitch.load(Ordering::Relaxed));
+
+            for (index, frame) in data.chunks_mut(channels).enumerate() {
+                // Generate sine wave, so our biiiip will be quite smooth
+                let val = ((index as f32 * pitch / 300.0).cos() + 1.0) / 2.0 * 4.0;
+
+                // Convert into the range of the sample type (-1.0..=1.0 for floats)
+                frame.fill(T::from_sample(val.clamp(-1.0, 1.0)));
+            }
+        },
+        // The stream is rebuilt on the next biiip (see [Buzzer::set_playing])
+        move |err| {
+            eprintln!("Buzzer runtime error: {}", err);
+            failed.store(true, Ordering::Relaxed);
+        },
+        None
+    )
+}
+
 /// Buzzer
 /// FIXME: Playing biiip may "click" because first sample in the stream buffer != 0,
 ///        so i need to somehow reset the stream buffer, before playing the biiip
 pub struct Buzzer {
     device: cpal::Device,
     config: cpal::StreamConfig,
+    sample_format: SampleFormat,
     stream: Option<cpal::Stream>,
+    /// Whether the stream couldn't be created, then the buzzer stays silent
+    broken: bool,
+    /// Set by the stream when it has failed, then it is rebuilt
+    stream_failed: Arc<AtomicBool>,
+    /// Frequency multiplier shared with the stream, stored as [f32] bits
+    pitch: Arc<AtomicU32>,
     pub muted: bool,
     playing: bool,
+    /// When the current biiip has started
+    started: Instant,
+    /// Biiip plays at least this long, even if the game asked for a shorter one
+    min_duration: Duration,
 }
+
+/// Returns names of all the available output devices
+pub fn output_device_names() -> Vec<String> {
+    let host = cpal::default_host();
+    let Ok(devices) = host.output_devices() else {
+        return vec![];
+    };
+
+    devices.filter_map(|d| d.name().ok()).collect()
+}
+
+/// Find an output device which name contains `name` (case-insensitive)
+fn find_output_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    let name = name.to_lowercase();
+
+    host.output_devices()
+        .ok()?
+        .find(|d| d.name().is_ok_and(|n| n.to_lowercase().contains(&name)))
+}
+
 impl Buzzer {
-    pub fn new() -> Result<Self, BuzzerError> {
+    /// Use the output device which name contains `device_name`,
+    /// or the default output device if it wasn't specified or found
+    pub fn new(device_name: Option<&str>) -> Result<Self, BuzzerError> {
         let host = cpal::default_host();
-        let device = host.default_output_device()
+
+        let device = device_name.and_then(|name| {
+            let device = find_output_device(&host, name);
+            if device.is_none() {
+                eprintln!("Audio device \"{}\" was not found, using the default one", name);
+            }
+            device
+        });
+        let device = device
+            .or_else(|| host.default_output_device())
             .ok_or(BuzzerError::NoOutputDevice)?;
 
-        let mut supported_configs_range = device.supported_output_configs()
-            .map_err(|_| BuzzerError::NoAvaliableConfigs)?;
-        let supported_config = supported_configs_range.next()
-            .ok_or(BuzzerError::NoAvaliableConfigs)?
+        let supported_configs: Vec<_> = device.supported_output_configs()
+            .map_err(|_| BuzzerError::NoAvaliableConfigs)?
+            .collect();
+        if supported_configs.is_empty() {
+            return Err(BuzzerError::NoAvaliableConfigs);
+        }
+
+        // Choose the first config with the most preferred sample format
+        let supported_config = supported_configs
+            .into_iter()
+            .filter_map(|c| {
+                let priority = SUPPORTED_FORMATS.iter().position(|f| *f == c.sample_format())?;
+                Some((priority, c))
+            })
+            .min_by_key(|(priority, _)| *priority)
+            .ok_or(BuzzerError::UnsupportedFormat)?
+            .1
             .with_max_sample_rate();
 
         Ok(Self {
             device,
+            sample_format: supported_config.sample_format(),
             config: supported_config.config(),
             stream: None,
+            broken: false,
+            stream_failed: Arc::new(AtomicBool::new(false)),
+            pitch: Arc::new(AtomicU32::new(1.0f32.to_bits())),
             muted: false,
             playing: false,
+            started: Instant::now(),
+            min_duration: Duration::ZERO,
         })
     }
 
+    /// Make the short biiips at least `duration` long, so the one frame blips are still audible
+    /// There is no envelope, the biiip is just kept playing, so it may still click
+    pub fn set_min_duration(&mut self, duration: Duration) {
+        self.min_duration = duration;
+    }
+
     pub fn set_muted(&mut self, state: bool) {
         self.muted = state;
         if state {
-            self.set_playing(false);
+            self.stop();
         }
     }
+    /// Multiply the biiip frequency by `pitch`
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch.store(pitch.to_bits(), Ordering::Relaxed);
+    }
+    /// Stopping takes effect only after the min duration of the biiip (see [Buzzer::set_min_duration]),
+    /// so keep calling it every frame
+    /// Failed stream is rebuilt when the biiip starts, so the sound comes back after the device hiccups
     pub fn set_playing(&mut self, state: bool) {
         // Do nothing if the state hasn't changed
         if self.playing == state { return; }
         // Do nothing if trying to enable playing while muted
         if state && self.muted { return; }
+        // There is nothing to play with, and trying again every frame would fail the same way
+        if state && self.broken { return; }
+        // Short biiip keeps playing for a bit
+        if !state && self.started.elapsed() < self.min_duration { return; }
 
-        self.playing = state;
+        self.set_stream_playing(state);
+    }
+    /// Stop playing right away, whatever the min duration is
+    fn stop(&mut self) {
+        if self.playing {
+            self.set_stream_playing(false);
+        }
+    }
+    fn set_stream_playing(&mut self, state: bool) {
         if state {
+            if self.stream_failed.swap(false, Ordering::Relaxed) && self.stream.take().is_some() {
+                eprintln!("Buzzer stream has failed, creating it again");
+            }
+
             // Create a stream if not already created
             // Stream starts playing on creation and i cant immediately pause it
             if self.stream.is_none() {
-                self.stream = Some(self.device.build_output_stream(
-                    &self.config,
-                    move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                        for (index, sample) in data.iter_mut().enumerate() {
-                            // Generate sine wave, so our biiiip will be quite smooth
-                            let val = ((index as f32 / 300.0).cos() + 1.0) / 2.0 * 4.0;
-
-                            *sample = val;
-                        }
-                    },
-                    // FIXME: Just print the error into the console for now
-                    |err| eprintln!("Buzzer runtime error: {}", err),
-                    None
-                ).unwrap());
+                let pitch = Arc::clone(&self.pitch);
+                let failed = Arc::clone(&self.stream_failed);
+                let stream = match self.sample_format {
+                    SampleFormat::I16 => build_stream::<i16>(&self.device, &self.config, pitch, failed),
+                    SampleFormat::U16 => build_stream::<u16>(&self.device, &self.config, pitch, failed),
+                    _ => build_stream::<f32>(&self.device, &self.config, pitch, failed),
+                };
+
+                match stream {
+                    Ok(stream) => {
+                        crate::debug!("Created the buzzer stream: {:?}, {:?}", self.sample_format, self.config);
+                        self.stream = Some(stream);
+                    }
+                    Err(e) => {
+                        eprintln!("Unable to create the buzzer stream: {}", e);
+                        eprintln!("Ignored. You won't be able to listen to biiip :(");
+                        self.broken = true;
+                        return;
+                    }
+                }
             }
 
-            let _ = self.stream.as_ref().unwrap().play();
+            self.playing = true;
+            self.started = Instant::now();
+            if let Some(stream) = &self.stream {
+                let _ = stream.play();
+            }
         } else {
+            self.playing = false;
             if let Some(stream) = &self.stream {
                 let _ = stream.pause();
             }
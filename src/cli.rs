@@ -1,6 +1,13 @@
 use std::{fmt::Display, fs, io, path::PathBuf};
 
-use crate::config::{Color, DrawStrategy, Palette};
+use winit::keyboard::KeyCode;
+
+use crate::{
+    config::{Backend, Color, DrawStrategy, FastForwardAudio, Palette, UiColors, WindowPos, MAX_SPEED, MAX_TURBO_RATE, MAX_UI_SCALE},
+    cpu::{MAX_DISPLAY_SIZE, MEMORY_CAPACITY, MIN_DISPLAY_SIZE},
+    keymap::{self, Action, Bindings},
+    quirks::{self, Quirks},
+};
 
 // Errors
 #[derive(Debug)]
@@ -12,6 +19,11 @@ pub enum CliError {
     NoSuchArg(String),
     NoArgValue(String),
     NonZeroSpeed,
+    InvalidTurboRate,
+    InvalidUiScale,
+    InvalidDisplaySize,
+    /// Key and both of the actions bound to it
+    KeyConflict(KeyCode, Action, Action),
 }
 // No, i dont want to use thiserror
 impl Display for CliError {
@@ -24,8 +36,147 @@ impl Display for CliError {
             Self::NoSuchArg(a) => write!(f, "No such argument \"{a}\""),
             Self::NoArgValue(a) => write!(f, "Expected a value for \"{a}\""),
             Self::NonZeroSpeed => write!(f, "Speed must be > 0"),
+            Self::InvalidTurboRate => write!(f, "Turbo rate must be from 1 to {MAX_TURBO_RATE}"),
+            Self::InvalidUiScale => write!(f, "UI scale must be from 1 to {MAX_UI_SCALE}"),
+            Self::InvalidDisplaySize => write!(f, "Display width and height must be from {MIN_DISPLAY_SIZE} to {MAX_DISPLAY_SIZE}"),
+            Self::KeyConflict(k, a, b) => write!(
+                f,
+                "Key \"{}\" is bound to both {} and {}",
+                keymap::key_name(*k).unwrap_or("?"),
+                a.name(),
+                b.name(),
+            ),
+        }
+    }
+}
+
+/// Parse a key name for [Bindings]
+/// Esc is taken by the fullscreen and pause, so it can't be bound
+fn parse_bound_key(name: &str) -> Option<KeyCode> {
+    keymap::parse_key(name).filter(|&k| k != KeyCode::Escape)
+}
+
+/// Parse a palette list similar to "#RRGGBB,#RRGGBB;..."
+/// Each palette may have border and UI box colors after the background, "#fg,#bg,#border,#ui",
+/// either of them may be empty, like "#fff,#000,,#333"
+pub fn parse_palettes(arg: &str, val: &str) -> Result<Vec<Palette>, CliError> {
+    let mut pals = vec![];
+    let color = |s: &str| Color::from_hex_str(s).ok_or(CliError::InvalidColor(s.into()));
+    let extra_color = |s: Option<&str>| s.filter(|s| !s.is_empty()).map(color).transpose();
+
+    for palette in val.split(';') {
+        let mut colors = palette.split(',');
+        let (Some(fg_str), Some(bg_str)) = (colors.next(), colors.next()) else {
+            return Err(CliError::InvalidArg(arg.into()));
+        };
+
+        let palette = Palette {
+            fg: color(fg_str)?,
+            bg: color(bg_str)?,
+            border: extra_color(colors.next())?,
+            ui: extra_color(colors.next())?,
+        };
+        if colors.next().is_some() {
+            return Err(CliError::InvalidArg(arg.into()));
         }
+
+        pals.push(palette);
+    }
+
+    Ok(pals)
+}
+
+/// Parse the plane colors similar to "#RRGGBB,#RRGGBB,#RRGGBB,#RRGGBB"
+pub fn parse_colors(arg: &str, val: &str) -> Result<[Color; 4], CliError> {
+    let colors = val
+        .split(',')
+        .map(|c| Color::from_hex_str(c).ok_or(CliError::InvalidColor(c.into())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    colors.try_into().map_err(|_| CliError::InvalidArg(arg.into()))
+}
+
+/// Max scale of the raw output frames (see [crate::app::RawOutput])
+const MAX_RAW_SCALE: u32 = 8;
+
+/// Parse a display size like "128x64"
+fn parse_display_size(val: &str) -> Result<(u32, u32), CliError> {
+    let invalid = || CliError::InvalidValue(val.into());
+
+    let (w, h) = val.split_once('x').ok_or_else(invalid)?;
+    let w = w.parse::<u32>().map_err(|_| invalid())?;
+    let h = h.parse::<u32>().map_err(|_| invalid())?;
+
+    let range = MIN_DISPLAY_SIZE..=MAX_DISPLAY_SIZE;
+    if !range.contains(&w) || !range.contains(&h) {
+        return Err(CliError::InvalidDisplaySize);
     }
+
+    Ok((w, h))
+}
+
+/// Parse the window position like "100,50"
+fn parse_window_pos(val: &str) -> Result<WindowPos, CliError> {
+    let invalid = || CliError::InvalidValue(val.into());
+
+    let (x, y) = val.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse::<i32>().map_err(|_| invalid())?;
+    let y = y.trim().parse::<i32>().map_err(|_| invalid())?;
+
+    Ok(WindowPos::At(x, y))
+}
+
+/// A value written to the CPU before the game starts
+#[derive(Debug, Clone, Copy)]
+pub enum Poke {
+    /// (address, value)
+    Memory(u16, u8),
+    /// (register, value)
+    Register(u8, u8),
+    DelayTimer(u8),
+    SoundTimer(u8),
+}
+impl Poke {
+    /// Parse a poke similar to "0x2A0=0xFF", "V5=10" or "DT=60"
+    pub fn parse(s: &str) -> Option<Self> {
+        let (target, value) = s.split_once('=')?;
+
+        match target.to_ascii_uppercase().as_str() {
+            "DT" => return Some(Self::DelayTimer(parse_num(value)?)),
+            "ST" => return Some(Self::SoundTimer(parse_num(value)?)),
+            _ => ()
+        }
+
+        match target.strip_prefix('V').or(target.strip_prefix('v')) {
+            Some(_) => Self::parse_register(s),
+            None => Self::parse_memory(s),
+        }
+    }
+    /// Parse a register poke similar to "5=0x0A" or "V5=10"
+    pub fn parse_register(s: &str) -> Option<Self> {
+        let (x, value) = s.split_once('=')?;
+        let x = x.strip_prefix('V').or(x.strip_prefix('v')).unwrap_or(x);
+        let x = u8::from_str_radix(x, 16).ok().filter(|x| *x <= 0xF)?;
+
+        Some(Self::Register(x, parse_num(value)?))
+    }
+    /// Parse a memory poke similar to "0x300=0xFF"
+    pub fn parse_memory(s: &str) -> Option<Self> {
+        let (addr, value) = s.split_once('=')?;
+        let addr = parse_num::<u16>(addr).filter(|addr| (*addr as usize) < MEMORY_CAPACITY)?;
+
+        Some(Self::Memory(addr, parse_num(value)?))
+    }
+}
+
+/// Parse a decimal or a hex (with "0x" prefix) number
+pub fn parse_num<T: TryFrom<u32>>(s: &str) -> Option<T> {
+    let num = match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<u32>().ok()?,
+    };
+
+    T::try_from(num).ok()
 }
 
 pub fn print_version() {
@@ -38,13 +189,88 @@ pub fn print_help() {
     println!("{}", env!("CARGO_PKG_AUTHORS"));
     println!();
     println!("USAGE:");
-    println!("    pitch1002 <GAME.ch8> [OPTIONS]");
+    println!("    pitch1002 <GAME.ch8|PACK.zip> [OPTIONS]");
     println!();
     println!("OPTIONS:");
+    println!("    --demo                      Play the built-in game (it is played anyway if no game is specified)");
     println!("    --palettes, -p <PALETTES>   Specify custom palette list separated by semicolons (see EXAMPLES)");
+    println!("    --add-palettes <PALETTES>   Same as --palettes, but adds them to the end of the list (builtin by default)");
+    println!("    --start-palette <N>         Start with the Nth palette of the list (1 is the first)");
+    println!("    --colors <auto|COLORS>      Colors of the display planes, 4 colors separated by commas (see EXAMPLES)");
+    println!("    --ui-colors <UI_COLORS>     Colors of the messages: auto (default, contrasts with the background),");
+    println!("                                palette or text and box colors, for example: #fff,#333");
     println!("    --speed, -s <SPEED>         How many cycles will make CPU in one frame (20 is default)");
+    println!("    --unsafe-speed              Allow speed above {MAX_SPEED}, for benchmarking only! The window may freeze");
+    println!("    --accurate-timing           Instructions take different time, like on the COSMAC VIP (speed 20 is VIP speed)");
+    println!("    --threaded                  Run the CPU on its own thread, keeps the window responsive at very high speeds");
     println!("    --mute                      Mute audio on start");
+    println!("    --ff-audio <MODE>           Sound while fast forwarding: mute (default), pitch or normal");
+    println!("    --min-beep <FRAMES>         Play even the shortest biiips at least FRAMES frames (0 is default)");
+    println!("    --mute-on-unfocus <on|off>  Silence the sound while the window is not focused (on is default)");
+    println!("    --pause-on-unfocus          Pause the game while the window is not focused");
+    println!("    --auto-pause <SECONDS>      Pause the game when nothing was pressed for SECONDS, any press unpauses it");
+    println!("    --blank-warning <SECONDS>   Hint at the wrong quirks if the game hasn't drawn anything for SECONDS (5 is default, 0 disables)");
+    println!("    --pause-clears-sound        Drop the rest of the biiip on pause, otherwise it plays after the pause");
+    println!("    --audio-device <NAME>       Play the sound on the device which name contains NAME");
+    println!("    --list-audio-devices        Print names of the available audio devices");
+    println!("    --remember                  Remember the palette, speed and mute on exit and restore them next time");
+    println!("    --exit-on-halt              Exit when the program ends (exit code is 1 if it has failed in strict mode)");
+    println!("    --log-ignored               Print the unknown instructions the game has tried to execute on exit");
+    println!("    --quirks, -q <QUIRKS>       Interpreter behaviour the game expects (see EXAMPLES)");
+    println!("    --list-quirks               Print all the quirks and what they do");
+    println!("    --auto-quirks               Guess the quirks by looking at the game, if they weren't specified");
+    println!("    --compare <GAME>            Run GAME on the right side of the window to compare it with the main game");
+    println!("    --compare-quirks <QUIRKS>   Run the game on the right side with QUIRKS (with --compare, that game)");
+    println!("    --strict                    Warn when the game executes or reads the memory it never wrote");
+    println!("    --seed <SEED>               Seed of the random numbers, the same seed gives the same numbers (1002 is default)");
+    println!("    --splash <FRAMES>           How many frames the splash screen is shown (60 is default)");
+    println!("    --no-splash                 Start the game right away, without the splash screen");
+    println!("    --display <WxH>             Non-standard display size of some CHIP-8 variants, for example 128x64 (64x32 is default)");
+    println!("    --ui-scale <SCALE>          Make the messages SCALE times bigger, from 1 to 3 (1 is default)");
     println!("    --draw-on-step              Redraw the screen on every CPU cycle, instead of once in a frame");
+    println!("    --vsync <on|off>            Sync the frames with the monitor, off may reduce the latency (on is default)");
+    println!("    --backend <BACKEND>         Graphics API to render with: vulkan, metal, dx12 or gl (chosen automatically by default)");
+    println!("    --smooth                    Smooth the upscaled pixels instead of keeping them crisp");
+    println!("    --fast-fade                 Cheaper, but less natural fading of the pixels in the fade draw strategy");
+    println!("    --afterglow                 Just turned off pixels glow for one more frame, reduces flickering");
+    println!("    --highlight-draws           Outline the last drawn sprite for a frame, for debugging (toggle with H)");
+    println!("    --timer                     Show the time and frames since the game was loaded or restarted (toggle with T)");
+    println!("    --defer-clear               Don't show the empty screen right after the clear, reduces flickering");
+    println!("    --cpu-meter                 Show how much of the frame cycles were executed (toggle with F12)");
+    println!("    --grid                      Draw thin lines between the pixels (toggle with F5)");
+    println!("    --grid-color <COLOR>        Color of the grid lines (#000 is default)");
+    println!("    --grid-opacity <PERCENT>    Opacity of the grid lines, from 0 to 100 (25 is default)");
+    println!("    --palette-fade <FRAMES>     Cross-fade the colors for FRAMES frames when the palette changes (0 is default)");
+    println!("    --pause-key <KEY>           Key that toggles pause, for example: p (default), pause, f5 or space");
+    println!("    --bind <ACTION=KEY>         Bind the action to another key, for example: fast-forward=tab (can be repeated)");
+    println!("                                Actions: pause, fast-forward, mute, next-palette, prev-palette,");
+    println!("                                random-palette, speed-up, speed-down, reset-speed, restart,");
+    println!("                                highlight-draws, timer, copy-screen");
+    println!("    --mouse-keypad              Press the buttons by clicking on the window (split into 4x4 keypad)");
+    println!("    --show-cursor               Don't hide the cursor while the game is running");
+    println!("    --window-pos <X,Y>          Place the window at X,Y on the screen (in physical pixels)");
+    println!("    --center                    Place the window at the center of the primary monitor");
+    println!("    --turbo <BUTTON>            Auto-fire the CHIP-8 button (0-F) while it is held");
+    println!("    --turbo-rate <RATE>         Auto-fire presses per second (15 is default)");
+    println!("    --attract                   Press random buttons when nobody is playing for 5 seconds");
+    println!("    --entry <ADDR>              Start the execution from ADDR, for example: 0x600 (0x200 is default)");
+    println!("    --poke <ADDR|Vx|DT|ST=VALUE> Write a byte to the memory, Vx or a timer before the game starts (can be repeated)");
+    println!("    --set-v <X=VALUE>           Set the register Vx before the game starts (can be repeated)");
+    println!("    --set-mem <ADDR=VALUE>      Write a byte to the memory before the game starts (can be repeated)");
+    println!("    --watch                     Reload the game when its file changes on disk");
+    println!("    --software <FILE>           Render on the CPU without the GPU, the frames are saved into FILE (PNG)");
+    println!("                                instead of the window, which only takes the input");
+    println!("    --raw-output <FILE|->       Stream the frames as raw RGBA pixels into FILE or stdout (-), for ffmpeg (see EXAMPLES)");
+    println!("    --raw-scale <SCALE>         Make the streamed frames SCALE times bigger, from 1 to 8 (1 is default)");
+    println!("    --dump-mem <FILE>           Write the whole memory into FILE on exit, including the changes made by the game");
+    println!("    --symbols <FILE>            Show the labels of the addresses in the debug output (\"<ADDR> <LABEL>\" lines)");
+    println!("    --record <FILE>             Record the pressed buttons into FILE to replay them later");
+    println!("    --replay <FILE>             Replay the buttons recorded with --record");
+    println!("    --warmup <CYCLES>           Run the game for CYCLES cycles before opening the window");
+    println!("    --bench <CYCLES>            Run the game for CYCLES cycles without a window and print how fast it was");
+    println!("    --snapshot <CYCLES> <FILE>  Run the game for CYCLES cycles without a window and save the screen into FILE (PNG)");
+    println!("    --smoke-test <DIR>          Briefly run every game in DIR in strict mode and print which of them fail");
+    println!("    --verbose                   Print debug logs, twice to print the logs of the libraries too");
     println!("    --help, -h                  Print this message!");
     println!("    --version, -v               Print version");
     println!("    --hello                     Say \"hello\"");
@@ -62,18 +288,52 @@ pub fn print_help() {
     println!("    └───┴───┴───┴───┘      └───┴───┴───┴───┘");
     println!();
     println!("    ┌─────┐");
-    println!("    │ ESC │       - Pause/unpause the game");
+    println!("    │ ESC │       - Pause/unpause the game (leaves the fullscreen first, if it is on)");
     println!("    ├───┬─┘");
+    println!("    │ P │         - Pause/unpause the game (all the keys below up to F1 can be changed with --bind)");
+    println!("    ├───┤");
     println!("    │ M │         - Mute/unmute");
     println!("    ├───┼───┐");
     println!("    │ [ │ ] │     - Previous/next palette");
-    println!("    ├───┼───┼───┐");
+    println!("    ├───┼───┘");
+    println!("    │ \\ │         - Random palette");
+    println!("    ├───┼───┬───┐");
     println!("    │ 0 │ - │ + │ - Reset/-/+ speed");
     println!("    ├───┴───┴───┤");
     println!("    │   SPACE   │ - Fast forward!");
     println!("    ├───────────┤");
-    println!("    │   ENTER   │ (during the pause) - Restart the game");
-    println!("    └───────────┘");
+    println!("    │   ENTER   │ (during the pause or after the end) - Restart the game");
+    println!("    ├───┬───────┘");
+    println!("    │ H │         - Outline the last drawn sprite (see --highlight-draws)");
+    println!("    ├───┤");
+    println!("    │ T │         - Toggle the speedrun timer (see --timer)");
+    println!("    ├───┴┐");
+    println!("    │ F1 │        - Copy the screen to the clipboard (saved to a file without a clipboard)");
+    println!("    ├────┤");
+    println!("    │ F2 │        - Next draw strategy (frame, step or fade)");
+    println!("    ├────┤");
+    println!("    │ F3 │        - Toggle vsync");
+    println!("    ├────┤");
+    println!("    │ F4 │        - New random seed (printed to the terminal)");
+    println!("    ├────┤");
+    println!("    │ F5 │        - Toggle the pixel grid");
+    println!("    ├────┤");
+    println!("    │ F6 │        - Clear the display (the game keeps running)");
+    println!("    ├────┤");
+    println!("    │ F7 │        - Print the registers to the terminal");
+    println!("    ├────┤");
+    println!("    │ F8 │        - Print the display to the terminal");
+    println!("    ├────┤");
+    println!("    │ F9 │        - (during the pause) Step a single instruction");
+    println!("    ├────┴┐");
+    println!("    │ F10 │       - (during the pause) Tick the delay/sound timers once");
+    println!("    ├─────┤");
+    println!("    │ F11 │       - Toggle fullscreen");
+    println!("    ├─────┤");
+    println!("    │ F12 │       - Toggle the CPU load meter");
+    println!("    ├─────┤");
+    println!("    │ TAB │       - Control both/left/right game (with --compare)");
+    println!("    └─────┘");
     println!();
     println!("EXAMPLES:");
     println!("    Launch PITCH1002 and scan current dir for .ch8 files");
@@ -88,32 +348,156 @@ pub fn print_help() {
     println!("    Multiple palettes, to change them in-game!");
     println!("        pitch1002 ./game.ch8 --palettes #fff,#000;#e0f8d0,#081820;#f00,#111");
     println!();
+    println!("    Palette may also set the colors around the game and of the message boxes (#fg,#bg,#border,#ui)");
+    println!("        pitch1002 ./game.ch8 --palettes #fff,#000,#222,#448");
+    println!("        pitch1002 ./game.ch8 --palettes #fff,#000,,#448");
+    println!();
+    println!("    Keep the builtin palettes and add your own to the end");
+    println!("        pitch1002 ./game.ch8 --add-palettes #fff,#000;#f00,#111");
+    println!();
+    println!("    Colors of the XO-CHIP display planes (#none,#first,#second,#both), or based on the palette");
+    println!("        pitch1002 ./game.ch8 --colors #000,#fff,#f80,#888");
+    println!("        pitch1002 ./game.ch8 --colors auto");
+    println!();
     println!("    Enabling --draw-on-step may fix sprites disapearing");
     println!("    This may happen when sprite redraws too often and screen just have no time to update");
     println!("    Can have a big impact on performance if game speed is too high!");
     println!("        pitch1002 ./oh-no.ch8 --draw-on-step");
+    println!();
+    println!("    Whole screen flickers? The game may clear and redraw it every frame, try --defer-clear");
+    println!("        pitch1002 ./flicker.ch8 --defer-clear");
+    println!();
+    println!("    Quirks can be a profile (chip8, schip, none) or a list of single quirks");
+    println!("    (shift, load-store, vf-reset, clip, jump, index-overflow, see --list-quirks). Try them if the game acts weird!");
+    println!("        pitch1002 ./game.ch8 --quirks chip8");
+    println!("        pitch1002 ./game.ch8 --quirks schip,vf-reset");
+    println!("        pitch1002 ./game.ch8 --auto-quirks");
+    println!();
+    println!("    Record a video with ffmpeg, the frame size is printed on start");
+    println!("    Messages go into stderr while streaming into stdout, so they don't break the video");
+    println!("        pitch1002 ./game.ch8 --raw-output - --raw-scale 8 | ffmpeg -f rawvideo -pixel_format rgba -video_size 512x256 -framerate 60 -i - out.mp4");
+    println!();
+    println!("    Run the game with two quirk profiles side by side to see the difference");
+    println!("        pitch1002 ./game.ch8 --quirks chip8 --compare-quirks schip");
+    println!();
+    println!("    Settings for a single game can be stored next to it in \"<GAME>.toml\" file");
+    println!("    Options passed in the command line always win");
+    println!("        speed = 30");
+    println!("        palettes = \"#fff,#000\"");
+    println!("        quirks = \"chip8\"");
+    println!("        afterglow = true");
+    println!();
+    println!("    Some options can be set with the environment variables, for the scripts and containers");
+    println!("    Options passed in the command line win, the variables win over the game settings");
+    println!("        PITCH1002_SPEED=30 PITCH1002_PALETTE=2 PITCH1002_MUTE=1 PITCH1002_SCALE=2 pitch1002 ./game.ch8");
+    println!();
+    println!("    Hold \"W\" (CHIP-8 button 5) to fire 20 times per second");
+    println!("        pitch1002 ./shooter.ch8 --turbo 5 --turbo-rate 20");
+    println!();
+    println!("    Cheat! Poke the memory before the game starts");
+    println!("        pitch1002 ./game.ch8 --poke 0x2A0=0xFF --poke V5=10");
+    println!("        pitch1002 ./game.ch8 --set-mem 0x300=0xFF --set-v 5=0x0A");
+    println!();
+    println!("    Developing your own game? Rebuild the ROM and it will be reloaded automatically");
+    println!("        pitch1002 ./my-game.ch8 --watch");
+    println!();
+    println!("    Label the addresses of the debugged game in F7, F8 and F9 output, each line is \"<ADDR> <LABEL>\"");
+    println!("        pitch1002 ./my-game.ch8 --symbols my-game.sym");
+    println!();
+    println!("    Record your best run and show it to your friends (same game and quirks are required)");
+    println!("        pitch1002 ./game.ch8 --record best.log");
+    println!("        pitch1002 ./game.ch8 --replay best.log");
+    println!();
+    println!("    Golden image of the game, the same game, seed and quirks always give the same image");
+    println!("        pitch1002 ./game.ch8 --seed 1 --quirks chip8 --snapshot 100000 game.png");
+    println!();
+    println!("    Measure the interpreter speed with the bundled benchmark game");
+    println!("        pitch1002 ./roms/bench.ch8 --bench 10000000");
 }
 
 /// Cli
 #[derive(Default)]
 pub struct Cli {
     pub game_paths: Option<Vec<PathBuf>>,
+    pub demo: bool,
     pub palettes: Option<Vec<Palette>>,
-    pub speed: Option<u16>,
+    /// Palettes appended to the list, instead of replacing it
+    pub extra_palettes: Vec<Palette>,
+    /// Starts from 1
+    pub start_palette: Option<usize>,
+    pub multicolor: bool,
+    pub colors: Option<[Color; 4]>,
+    pub ui_colors: UiColors,
+    pub speed: Option<u32>,
+    pub unsafe_speed: bool,
+    pub accurate_timing: bool,
+    pub threaded: bool,
     pub mute: bool,
+    pub quirks: Option<Quirks>,
+    pub auto_quirks: bool,
+    pub compare_game: Option<PathBuf>,
+    pub compare_quirks: Option<Quirks>,
+    pub strict: bool,
+    pub seed: Option<u32>,
+    pub entry: Option<u16>,
+    pub splash_frames: Option<u16>,
+    pub ui_scale: Option<u8>,
+    pub display_size: Option<(u32, u32)>,
     pub draw_strategy: DrawStrategy,
+    pub vsync: Option<bool>,
+    pub backend: Option<Backend>,
+    pub smooth: bool,
+    pub fast_fade: bool,
+    pub afterglow: bool,
+    pub highlight_draws: bool,
+    pub timer: bool,
+    pub defer_clear: bool,
+    pub palette_fade: Option<u8>,
+    pub cpu_meter: bool,
+    pub grid: bool,
+    pub grid_color: Option<Color>,
+    pub grid_opacity: Option<u8>,
+    pub watch: bool,
+    pub remember: bool,
+    pub exit_on_halt: bool,
+    pub log_ignored: bool,
+    pub ff_audio: FastForwardAudio,
+    pub min_beep: Option<u8>,
+    pub mute_on_unfocus: Option<bool>,
+    pub pause_on_unfocus: bool,
+    pub pause_clears_sound: bool,
+    pub auto_pause: Option<u32>,
+    pub blank_warning: Option<u32>,
+    pub audio_device: Option<String>,
+    pub list_audio_devices: bool,
+    pub bindings: Bindings,
+    pub mouse_keypad: bool,
+    pub show_cursor: bool,
+    pub window_pos: WindowPos,
+    pub turbo_btn: Option<u8>,
+    pub turbo_rate: Option<u8>,
+    pub attract: bool,
+    pub pokes: Vec<Poke>,
+    pub software: Option<PathBuf>,
+    pub raw_output: Option<PathBuf>,
+    pub raw_scale: Option<u32>,
+    pub dump_mem: Option<PathBuf>,
+    pub symbols: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub bench: Option<u64>,
+    /// Cycles to run and the image to write
+    pub snapshot: Option<(u64, PathBuf)>,
+    pub smoke_test: Option<PathBuf>,
+    pub warmup: Option<u64>,
+    /// 0 - no logs, 1 - debug logs, 2 - all the logs
+    pub verbose: u8,
 }
 impl Cli {
     pub fn new() -> Result<Self, CliError> {
         let mut args = std::env::args().skip(1);
 
-        let mut cli = Self {
-            game_paths: None,
-            palettes: None,
-            speed: None,
-            mute: false,
-            draw_strategy: DrawStrategy::default()
-        };
+        let mut cli = Self::default();
 
         // Parse args
         while let Some(arg) = args.next() {
@@ -127,40 +511,65 @@ impl Cli {
                     print_version();
                     std::process::exit(0);
                 }
+                "--verbose" => {
+                    cli.verbose = cli.verbose.saturating_add(1);
+                }
                 "--hello" => {
                     println!("hi!");
                     std::process::exit(0);
                 }
 
+                "--demo" => {
+                    cli.demo = true;
+                }
+
                 "--palettes" | "-p" => {
-                    let mut pals = vec![];
                     let val = args.next()
                         .ok_or(CliError::NoArgValue(arg.clone()))?;
 
-                    // Parse value similar to "#RRGGBB,#RRGGBB;..."
-                    for palette in val.split(';') {
-                        let (fg_str, bg_str) = palette
-                            .split_once(',')
-                            .ok_or(CliError::InvalidArg(arg.clone()))?;
+                    cli.palettes = Some(parse_palettes(&arg, &val)?);
+                }
+                "--add-palettes" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.extra_palettes.extend(parse_palettes(&arg, &val)?);
+                }
+                "--start-palette" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.start_palette = Some(val.parse().map_err(|_| CliError::InvalidValue(val))?);
+                }
 
-                        let palette = (
-                            Color::from_hex_str(fg_str)
-                                .ok_or(CliError::InvalidColor(fg_str.into()))?,
-                            Color::from_hex_str(bg_str)
-                                .ok_or(CliError::InvalidColor(bg_str.into()))?
-                        );
+                "--colors" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
 
-                        pals.push(palette);
+                    cli.multicolor = true;
+                    if val != "auto" {
+                        cli.colors = Some(parse_colors(&arg, &val)?);
                     }
+                }
+                "--ui-colors" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
 
-                    cli.palettes = Some(pals);
+                    cli.ui_colors = match val.as_str() {
+                        "auto" => UiColors::Auto,
+                        "palette" => UiColors::Palette,
+                        _ => match parse_palettes(&arg, &val)?.as_slice() {
+                            [Palette { fg, bg, border: None, ui: None }] => UiColors::Custom(fg.clone(), bg.clone()),
+                            _ => return Err(CliError::InvalidValue(val)),
+                        },
+                    };
                 }
 
                 "--speed" | "-s" => {
                     let val = args.next()
                         .ok_or(CliError::NoArgValue(arg.clone()))?;
                     let num = val
-                        .parse::<u16>()
+                        .parse::<u32>()
                         .map_err(|_| CliError::InvalidValue(val))?;
 
                     if num == 0 {
@@ -169,14 +578,419 @@ impl Cli {
 
                     cli.speed = Some(num);
                 }
+                "--unsafe-speed" => {
+                    cli.unsafe_speed = true;
+                }
+
+                "--accurate-timing" => {
+                    cli.accurate_timing = true;
+                }
+                "--threaded" => {
+                    cli.threaded = true;
+                }
 
                 "--mute" => {
                     cli.mute = true;
                 }
+                "--remember" => {
+                    cli.remember = true;
+                }
+                "--exit-on-halt" => {
+                    cli.exit_on_halt = true;
+                }
+                "--log-ignored" => {
+                    cli.log_ignored = true;
+                }
+                "--ff-audio" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.ff_audio = FastForwardAudio::parse(&val)
+                        .ok_or(CliError::InvalidValue(val))?;
+                }
+                "--pause-key" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    let key = parse_bound_key(&val).ok_or(CliError::InvalidValue(val))?;
+                    cli.bindings.set(Action::Pause, key);
+                }
+                "--bind" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    let (action, key) = val.split_once('=')
+                        .and_then(|(action, key)| Some((Action::parse(action)?, parse_bound_key(key)?)))
+                        .ok_or(CliError::InvalidValue(val))?;
+                    cli.bindings.set(action, key);
+                }
+                "--min-beep" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let frames = val
+                        .parse::<u8>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.min_beep = Some(frames);
+                }
+                "--mute-on-unfocus" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.mute_on_unfocus = match val.as_str() {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        _ => return Err(CliError::InvalidValue(val))
+                    };
+                }
+                "--pause-on-unfocus" => {
+                    cli.pause_on_unfocus = true;
+                }
+                "--pause-clears-sound" => {
+                    cli.pause_clears_sound = true;
+                }
+                "--auto-pause" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let secs = val
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|s| *s > 0)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.auto_pause = Some(secs);
+                }
+                "--blank-warning" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let secs = val
+                        .parse::<u32>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.blank_warning = Some(secs);
+                }
+                "--audio-device" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.audio_device = Some(val);
+                }
+                "--list-audio-devices" => {
+                    cli.list_audio_devices = true;
+                }
+
+                "--quirks" | "-q" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.quirks = Some(Quirks::parse(&val).ok_or(CliError::InvalidValue(val))?);
+                }
+                "--auto-quirks" => {
+                    cli.auto_quirks = true;
+                }
+                "--compare" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.compare_game = Some(PathBuf::from(val));
+                }
+                "--compare-quirks" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.compare_quirks = Some(Quirks::parse(&val).ok_or(CliError::InvalidValue(val))?);
+                }
+                "--list-quirks" => {
+                    quirks::print_list();
+                    std::process::exit(0);
+                }
+                "--strict" => {
+                    cli.strict = true;
+                }
+                "--seed" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let seed = val
+                        .parse::<u32>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.seed = Some(seed);
+                }
+                "--entry" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    // The whole instruction must fit in the memory
+                    let addr = parse_num::<u16>(&val)
+                        .filter(|addr| (*addr as usize) + 1 < MEMORY_CAPACITY)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.entry = Some(addr);
+                }
+
+                "--splash" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let frames = val
+                        .parse::<u16>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.splash_frames = Some(frames);
+                }
+                "--no-splash" => {
+                    cli.splash_frames = Some(0);
+                }
+                "--display" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.display_size = Some(parse_display_size(&val)?);
+                }
+                "--ui-scale" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let scale = val
+                        .parse::<u8>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    if scale == 0 || scale > MAX_UI_SCALE {
+                        return Err(CliError::InvalidUiScale);
+                    }
+
+                    cli.ui_scale = Some(scale);
+                }
 
                 "--draw-on-step" => {
                     cli.draw_strategy = DrawStrategy::Step;
                 }
+                "--vsync" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.vsync = match val.as_str() {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        _ => return Err(CliError::InvalidValue(val))
+                    };
+                }
+                "--backend" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    // Rendering still works with the automatically chosen backend
+                    cli.backend = Backend::parse(&val);
+                    if cli.backend.is_none() {
+                        eprintln!("Unknown backend \"{}\", using the default one", val);
+                    }
+                }
+                "--smooth" => {
+                    cli.smooth = true;
+                }
+                "--fast-fade" => {
+                    cli.fast_fade = true;
+                }
+                "--afterglow" => {
+                    cli.afterglow = true;
+                }
+                "--highlight-draws" => {
+                    cli.highlight_draws = true;
+                }
+                "--timer" => {
+                    cli.timer = true;
+                }
+                "--defer-clear" => {
+                    cli.defer_clear = true;
+                }
+                "--cpu-meter" => {
+                    cli.cpu_meter = true;
+                }
+                "--grid" => {
+                    cli.grid = true;
+                }
+                "--grid-color" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let color = Color::from_hex_str(&val)
+                        .ok_or(CliError::InvalidColor(val))?;
+
+                    cli.grid_color = Some(color);
+                }
+                "--grid-opacity" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let opacity = val
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|o| *o <= 100)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.grid_opacity = Some(opacity);
+                }
+                "--palette-fade" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let frames = val
+                        .parse::<u8>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.palette_fade = Some(frames);
+                }
+
+                "--mouse-keypad" => {
+                    cli.mouse_keypad = true;
+                }
+                "--show-cursor" => {
+                    cli.show_cursor = true;
+                }
+                "--window-pos" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    cli.window_pos = parse_window_pos(&val)?;
+                }
+                "--center" => {
+                    cli.window_pos = WindowPos::Center;
+                }
+
+                "--turbo" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let btn = u8::from_str_radix(&val, 16)
+                        .ok()
+                        .filter(|btn| *btn <= 0xF)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.turbo_btn = Some(btn);
+                }
+                "--turbo-rate" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let rate = val
+                        .parse::<u8>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    if rate == 0 || rate > MAX_TURBO_RATE {
+                        return Err(CliError::InvalidTurboRate);
+                    }
+
+                    cli.turbo_rate = Some(rate);
+                }
+
+                "--attract" => {
+                    cli.attract = true;
+                }
+
+                "--poke" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let poke = Poke::parse(&val)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.pokes.push(poke);
+                }
+                "--set-v" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let poke = Poke::parse_register(&val)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.pokes.push(poke);
+                }
+                "--set-mem" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let poke = Poke::parse_memory(&val)
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.pokes.push(poke);
+                }
+
+                "--watch" => {
+                    cli.watch = true;
+                }
+
+                "--dump-mem" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.dump_mem = Some(val.into());
+                }
+                "--symbols" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.symbols = Some(val.into());
+                }
+                "--record" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.record = Some(val.into());
+                }
+                "--replay" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.replay = Some(val.into());
+                }
+
+                "--warmup" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let cycles = val
+                        .parse::<u64>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.warmup = Some(cycles);
+                }
+
+                "--bench" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let cycles = val
+                        .parse::<u64>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+
+                    cli.bench = Some(cycles);
+                }
+                "--snapshot" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let cycles = val
+                        .parse::<u64>()
+                        .map_err(|_| CliError::InvalidValue(val))?;
+                    let path = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.snapshot = Some((cycles, PathBuf::from(path)));
+                }
+                "--software" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.software = Some(val.into());
+                }
+                "--raw-output" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.raw_output = Some(PathBuf::from(val));
+                }
+                "--raw-scale" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+                    let scale = val
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|s| (1..=MAX_RAW_SCALE).contains(s))
+                        .ok_or(CliError::InvalidValue(val))?;
+
+                    cli.raw_scale = Some(scale);
+                }
+                "--smoke-test" => {
+                    let val = args.next()
+                        .ok_or(CliError::NoArgValue(arg.clone()))?;
+
+                    cli.smoke_test = Some(PathBuf::from(val));
+                }
 
                 arg if arg.starts_with('-') => return Err(CliError::NoSuchArg(arg.into())),
 
@@ -196,8 +1010,8 @@ impl Cli {
                                 .map_err(CliError::Io)?;
                             let entry_path = entry.path();
 
-                            // Allow only files ending with .ch8
-                            let is_chip = entry_path.extension().is_some_and(|e| e == "ch8");
+                            // Allow only files ending with .ch8 and ROM packs
+                            let is_chip = entry_path.extension().is_some_and(|e| e == "ch8" || e == "zip");
                             if entry_path.is_file() && is_chip {
                                 paths.push(entry_path);
                             }
@@ -209,6 +1023,9 @@ impl Cli {
             }
         }
 
+        if let Some((a, b)) = cli.bindings.conflict() {
+            return Err(CliError::KeyConflict(cli.bindings.key(a), a, b));
+        }
 
         Ok(cli)
     }
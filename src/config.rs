@@ -1,4 +1,13 @@
-use crate::cli::Cli;
+use std::{ffi::OsString, fmt::Display, fs, io, path::{Path, PathBuf}};
+
+use crate::{
+    cli::{self, Cli},
+    cpu::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    keymap::Bindings,
+    quirks::Quirks,
+    rng::{Rng, DEFAULT_SEED},
+    symbols::{Symbols, SymbolsError},
+};
 
 // Macros
 #[macro_export]
@@ -16,12 +25,36 @@ macro_rules! color_hex {
 #[macro_export]
 macro_rules! palette {
     ($fg:expr, $bg:expr) => {
-        (crate::color_hex!($fg), crate::color_hex!($bg))
+        crate::config::Palette::new(crate::color_hex!($fg), crate::color_hex!($bg))
     };
 }
 
-// Types
-pub type Palette = (Color, Color);
+// Errors
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    InvalidLine(PathBuf, usize),
+    InvalidValue(PathBuf, String),
+    NoSuchKey(PathBuf, String),
+    /// Palette number (starting from 1) and the number of palettes
+    NoSuchPalette(usize, usize),
+    /// Name of the environment variable
+    InvalidEnv(&'static str),
+    Symbols(SymbolsError),
+}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(p, e) => write!(f, "Unable to read {}: {e}", p.display()),
+            Self::InvalidLine(p, l) => write!(f, "{}:{l}: Expected \"key = value\"", p.display()),
+            Self::InvalidValue(p, k) => write!(f, "{}: Invalid value of \"{k}\"", p.display()),
+            Self::NoSuchKey(p, k) => write!(f, "{}: No such key \"{k}\"", p.display()),
+            Self::NoSuchPalette(i, len) => write!(f, "No palette {i}, there are only {len} palettes"),
+            Self::InvalidEnv(name) => write!(f, "Invalid value of environment variable {name}"),
+            Self::Symbols(e) => write!(f, "{e}"),
+        }
+    }
+}
 
 // Consts
 /// FEEL FREE TO ADD YOUR OWN PALETTE!
@@ -58,13 +91,38 @@ const DEFAULT_PALETTES: [Palette; 15] = [
     // https://lospec.com/palette-list/blessing
     palette!(0xd8bfd8, 0x74569b),
 ];
-pub const MAX_SPEED: u16 = 40000;
-pub const DEFAULT_SPEED: u16 = 20;
+pub const MAX_SPEED: u32 = 40000;
+pub const DEFAULT_SPEED: u32 = 20;
+/// How many frames the splash screen is shown
+pub const DEFAULT_SPLASH_FRAMES: u16 = 60;
+/// Max size of a UI font pixel, bigger text doesn't fit the screen
+pub const MAX_UI_SCALE: u8 = 3;
+/// Opacity of the pixel grid lines in percents, they shouldn't get in the way of the game
+pub const DEFAULT_GRID_OPACITY: u8 = 25;
+/// Seconds without any drawing before the wrong quirks are suspected
+const DEFAULT_BLANK_WARNING: u32 = 5;
+/// How far the UI boxes are mixed from the background with [UiColors::Auto],
+/// they stand out from the game, but the text is still easy to read on them
+const UI_BOX_CONTRAST: f32 = 0.15;
+/// Auto-fire presses per second
+pub const DEFAULT_TURBO_RATE: u8 = 15;
+/// Max auto-fire presses per second (button is toggled every frame)
+pub const MAX_TURBO_RATE: u8 = 30;
+
+/// Convert an sRGB channel to linear space, from 0.0 to 1.0
+fn to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 }
+    else { ((c + 0.055) / 1.055).powf(2.4) }
+}
 
 /// Color
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Color(pub u8, pub u8, pub u8);
 impl Color {
+    pub const BLACK: Self = Self(0, 0, 0);
+    pub const WHITE: Self = Self(255, 255, 255);
+
     pub fn from_hex_str(s: &str) -> Option<Color> {
         let hex = s.strip_prefix('#')?;
         if !hex.is_ascii() { return None }
@@ -87,6 +145,66 @@ impl Color {
             None
         }
     }
+
+    /// Mix two colors, `t` is from 0.0 (self) to 1.0 (other)
+    pub fn mix(&self, other: &Color, t: f32) -> Color {
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        Self(lerp(self.0, other.0), lerp(self.1, other.1), lerp(self.2, other.2))
+    }
+    /// Same as [Color::mix], but the colors are mixed in linear space instead of sRGB,
+    /// so the mixed colors don't look muddy and gray
+    pub fn mix_linear(&self, other: &Color, t: f32) -> Color {
+        fn to_srgb(c: f32) -> u8 {
+            let c =
+                if c <= 0.0031308 { c * 12.92 }
+                else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+            (c * 255.0).round().clamp(0.0, 255.0) as u8
+        }
+        let lerp = |a: u8, b: u8| {
+            let (a, b) = (to_linear(a), to_linear(b));
+            to_srgb(a + (b - a) * t)
+        };
+
+        Self(lerp(self.0, other.0), lerp(self.1, other.1), lerp(self.2, other.2))
+    }
+    /// Relative luminance, from 0.0 (black) to 1.0 (white)
+    pub fn luminance(&self) -> f32 {
+        0.2126 * to_linear(self.0) + 0.7152 * to_linear(self.1) + 0.0722 * to_linear(self.2)
+    }
+}
+
+/// Colors of the game, optionally with the colors of the things around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub fg: Color,
+    pub bg: Color,
+    /// Color of the window around the displays, background by default
+    pub border: Option<Color>,
+    /// Color of the UI boxes with [UiColors::Auto], the text contrasts with it
+    pub ui: Option<Color>,
+}
+impl Palette {
+    pub const fn new(fg: Color, bg: Color) -> Self {
+        Self { fg, bg, border: None, ui: None }
+    }
+    pub fn border(&self) -> Color {
+        self.border.clone().unwrap_or_else(|| self.bg.clone())
+    }
+}
+
+/// Colors of the UI messages and boxes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum UiColors {
+    /// White or black text on the boxes a bit lighter or darker than the background,
+    /// depending on how bright the background of the palette is
+    /// Box color of the palette is used instead, if it has one (see [Palette::ui])
+    #[default]
+    Auto,
+    /// Colors of the palette, the boxes then look just like the empty game screen
+    Palette,
+    /// Text and box colors
+    Custom(Color, Color),
 }
 
 /// When to draw the screen
@@ -100,38 +218,501 @@ pub enum DrawStrategy {
     /// May fix sprites "disapearing". Sometimes the sprites can be redrawn several times per
     /// frame, which is provokes the disapearance (the screen just doesn't have time to update)
     /// Can have a big impact on performance if game speed is too high!
-    Step
+    /// The only strategy that shows the display halfway into the frame, the others show only whole frames
+    Step,
+    /// Draw the screen every frame, but turned off pixels fade out slowly
+    /// Hides the flickering of sprites redrawn every frame, just like an old CRT screen
+    Fade,
+}
+impl DrawStrategy {
+    /// Next strategy to switch to in-game
+    pub fn next(self) -> Self {
+        match self {
+            Self::Frame => Self::Step,
+            Self::Step => Self::Fade,
+            Self::Fade => Self::Frame,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Frame => "frame",
+            Self::Step => "step",
+            Self::Fade => "fade",
+        }
+    }
+}
+
+/// Read a file with `key = value` lines (a tiny subset of TOML) and `#` comments
+/// Returns `None` if the file doesn't exist
+fn read_kv_file(path: &Path) -> Result<Option<Vec<(String, String)>>, ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ConfigError::Io(path.into(), e)),
+    };
+
+    let mut pairs = vec![];
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+
+        let (key, val) = line
+            .split_once('=')
+            .ok_or(ConfigError::InvalidLine(path.into(), line_idx + 1))?;
+        let val = val.trim();
+
+        // Strip quotes or a trailing comment
+        let val = match val.strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+            None => val.split('#').next().unwrap_or_default().trim(),
+        };
+
+        pairs.push((key.trim().to_string(), val.to_string()));
+    }
+
+    Ok(Some(pairs))
+}
+
+/// Settings for a single game, stored next to it in "<GAME>.toml" file
+///
+/// ```toml
+/// speed = 30
+/// palettes = "#fff,#000"
+/// quirks = "chip8"
+/// afterglow = true
+/// ```
+#[derive(Debug, Default)]
+pub struct RomConfig {
+    pub palettes: Option<Vec<Palette>>,
+    pub speed: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub afterglow: Option<bool>,
+}
+impl RomConfig {
+    /// Load the settings for the game at `game_path`
+    /// Returns default (empty) settings if the game has no settings file
+    pub fn load(game_path: &Path) -> Result<Self, ConfigError> {
+        let mut path = OsString::from(game_path);
+        path.push(".toml");
+        let path = PathBuf::from(path);
+
+        let mut config = Self::default();
+        let Some(pairs) = read_kv_file(&path)? else {
+            return Ok(config);
+        };
+
+        for (key, val) in pairs {
+            let invalid = || ConfigError::InvalidValue(path.clone(), key.clone());
+
+            match key.as_str() {
+                "palettes" | "palette" => {
+                    config.palettes = Some(cli::parse_palettes(&key, &val).map_err(|_| invalid())?);
+                }
+                "speed" => {
+                    let speed = val.parse::<u32>().map_err(|_| invalid())?;
+                    if speed == 0 {
+                        return Err(invalid());
+                    }
+
+                    config.speed = Some(speed);
+                }
+                "quirks" => {
+                    config.quirks = Some(Quirks::parse(&val).ok_or_else(invalid)?);
+                }
+                "afterglow" => {
+                    config.afterglow = Some(val.parse().map_err(|_| invalid())?);
+                }
+                _ => return Err(ConfigError::NoSuchKey(path, key))
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Preferences remembered between launches (see `--remember`)
+/// Stored in "$XDG_CONFIG_HOME/pitch1002/state.toml"
+#[derive(Debug, Default)]
+pub struct SavedState {
+    pub palette_index: Option<usize>,
+    pub speed: Option<u32>,
+    pub muted: Option<bool>,
+}
+impl SavedState {
+    fn path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+
+        Some(config_dir.join("pitch1002").join("state.toml"))
+    }
+
+    /// Load the saved state
+    /// Returns default (empty) state if nothing was saved yet
+    /// Unknown keys and invalid values are ignored, the state will be overwritten on exit anyway
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut state = Self::default();
+        let Some(path) = Self::path() else {
+            return Ok(state);
+        };
+        let Some(pairs) = read_kv_file(&path)? else {
+            return Ok(state);
+        };
+
+        for (key, val) in pairs {
+            match key.as_str() {
+                "palette_index" => state.palette_index = val.parse().ok(),
+                "speed" => state.speed = val.parse().ok().filter(|s| *s > 0),
+                "muted" => state.muted = val.parse().ok(),
+                _ => ()
+            }
+        }
+
+        Ok(state)
+    }
+    /// Write the state to the disk
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+
+        let mut text = String::new();
+        if let Some(index) = self.palette_index {
+            text.push_str(&format!("palette_index = {}\n", index));
+        }
+        if let Some(speed) = self.speed {
+            text.push_str(&format!("speed = {}\n", speed));
+        }
+        if let Some(muted) = self.muted {
+            text.push_str(&format!("muted = {}\n", muted));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| ConfigError::Io(dir.into(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| ConfigError::Io(path, e))
+    }
+}
+
+/// Settings from the environment variables, for the scripts and containers
+///
+/// ```sh
+/// PITCH1002_SPEED=30     # same as --speed
+/// PITCH1002_PALETTE=2    # same as --start-palette
+/// PITCH1002_MUTE=1       # same as --mute, 0 unmutes the remembered mute
+/// PITCH1002_SCALE=2      # same as --ui-scale
+/// ```
+///
+/// Empty variables are ignored
+#[derive(Debug, Default)]
+pub struct EnvConfig {
+    pub speed: Option<u32>,
+    /// Starts from 1
+    pub start_palette: Option<usize>,
+    pub mute: Option<bool>,
+    pub ui_scale: Option<u8>,
+}
+impl EnvConfig {
+    pub fn load() -> Result<Self, ConfigError> {
+        fn var<T>(name: &'static str, parse: impl Fn(&str) -> Option<T>) -> Result<Option<T>, ConfigError> {
+            match std::env::var(name) {
+                Ok(val) if val.is_empty() => Ok(None),
+                Ok(val) => parse(&val).map(Some).ok_or(ConfigError::InvalidEnv(name)),
+                Err(std::env::VarError::NotPresent) => Ok(None),
+                Err(std::env::VarError::NotUnicode(_)) => Err(ConfigError::InvalidEnv(name)),
+            }
+        }
+
+        Ok(Self {
+            speed: var("PITCH1002_SPEED", |v| v.parse().ok().filter(|s| *s > 0))?,
+            start_palette: var("PITCH1002_PALETTE", |v| v.parse().ok())?,
+            mute: var("PITCH1002_MUTE", |v| match v {
+                "1" | "true" | "yes" => Some(true),
+                "0" | "false" | "no" => Some(false),
+                _ => None,
+            })?,
+            ui_scale: var("PITCH1002_SCALE", |v| v.parse().ok().filter(|s| (1..=MAX_UI_SCALE).contains(s)))?,
+        })
+    }
+}
+
+/// What to do with the sound while fast forwarding
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FastForwardAudio {
+    /// Don't play the sound at all
+    #[default]
+    Mute,
+    /// Raise the pitch proportionally to the speed
+    Pitch,
+    /// Play the sound as usual
+    Normal,
+}
+impl FastForwardAudio {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mute" => Some(Self::Mute),
+            "pitch" => Some(Self::Pitch),
+            "normal" => Some(Self::Normal),
+            _ => None
+        }
+    }
+}
+
+/// Graphics API used for the rendering (see [Config::backend])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+impl Backend {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "vulkan" => Some(Self::Vulkan),
+            "metal" => Some(Self::Metal),
+            "dx12" => Some(Self::Dx12),
+            "gl" => Some(Self::Gl),
+            _ => None
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vulkan => "vulkan",
+            Self::Metal => "metal",
+            Self::Dx12 => "dx12",
+            Self::Gl => "gl",
+        }
+    }
+}
+
+/// Where the window appears
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPos {
+    /// Wherever the OS decides
+    #[default]
+    Default,
+    /// Center of the primary monitor
+    Center,
+    /// Top left corner of the window in physical pixels
+    At(i32, i32),
 }
 
 /// Config
 #[derive(Debug)]
 pub struct Config {
     pub palettes: Vec<Palette>,
-    /// (foreground, background)
     pub palette: Palette,
     pub cur_palette_index: usize,
+    /// Whether pixels are colored by their display planes (XO-CHIP)
+    /// Otherwise (monochrome mode) the pixel is either foreground or background
+    pub multicolor: bool,
+    /// Colors of the plane combinations in multicolor mode:
+    /// none, first, second and both planes
+    /// If not specified, the colors are based on the current palette
+    pub colors: Option<[Color; 4]>,
+    pub ui_colors: UiColors,
 
-    pub speed: u16,
+    pub speed: u32,
+    /// Speed isn't limited by [MAX_SPEED], the window may freeze at very high speeds
+    pub unsafe_speed: bool,
+    /// Instructions take different time to execute, just like on the COSMAC VIP
+    /// Speed then scales the machine cycles of a frame, 20 is the VIP speed (see [crate::timing])
+    pub accurate_timing: bool,
+    /// Step the CPU on its own thread, so the window stays responsive at very high speeds
+    /// The screen is then rendered once in a frame for every draw strategy
+    pub threaded: bool,
+    pub quirks: Quirks,
+    /// Warn about suspicious behaviour of the game (see [crate::cpu::Cpu::strict])
+    pub strict: bool,
+    /// Seed of the random numbers, the game gets the same numbers every time with the same seed
+    pub seed: u32,
+    /// Address the execution starts from, 0x200 if not set
+    pub entry: Option<u16>,
 
-    pub draw_strategy: DrawStrategy
+    /// How many frames the splash screen is shown before the game starts (0 - no splash screen)
+    pub splash_frames: u16,
+    /// Width and height of the CHIP-8 display, only a few variants of CHIP-8 had a non-standard one
+    pub display_size: (u32, u32),
+    /// Size of a UI font pixel in display pixels, makes the messages readable on big screens
+    /// Longer messages are cut off when scaled
+    pub ui_scale: u8,
+    pub draw_strategy: DrawStrategy,
+    /// Present the frames in sync with the monitor, avoids the tearing
+    /// Frames are still emulated every [crate::app::TARGET_DELAY] milliseconds, so with vsync
+    /// the frame may be shown up to one monitor refresh later, and without it the frame is
+    /// shown right away, but may tear
+    pub vsync: bool,
+    /// Graphics API to render with, chosen automatically if not specified or unavailable
+    pub backend: Option<Backend>,
+    /// Render on the CPU into this PNG file instead of the window, for the machines without a GPU
+    pub software: Option<PathBuf>,
+    /// Upscale the display with the linear filtering, the pixels become blurry instead of crisp
+    pub smooth: bool,
+    /// Mix the fading pixels colors in sRGB space, which is cheaper but looks muddy
+    /// (see [DrawStrategy::Fade])
+    pub fast_fade: bool,
+    /// Just turned off pixels are drawn dimmed for one more frame, hides the flickering
+    /// of the sprites that are erased and redrawn (see [DrawStrategy::Fade] for a stronger effect)
+    pub afterglow: bool,
+    /// Outline the last drawn sprite for a frame, shows where exactly the game draws
+    pub highlight_draws: bool,
+    /// Show the frames and the time at 60 Hz since the game was loaded or restarted, for speedruns
+    pub timer: bool,
+    /// Don't show the empty screen after the clear until the game draws something
+    /// (but no longer than a frame) to reduce flickering
+    pub defer_clear: bool,
+    /// Draw a bar at the top of the screen, that shows how many cycles of the frame were executed
+    /// It blinks while the frames are cut short, because the CPU can't keep up
+    pub cpu_meter: bool,
+    /// Draw thin lines between the CHIP-8 pixels
+    pub grid: bool,
+    pub grid_color: Color,
+    /// From 0.0 (invisible) to 1.0
+    pub grid_opacity: f32,
+    /// How many frames the game colors cross-fade into the new palette (0 - no fading)
+    /// UI is always drawn with the new palette
+    pub palette_fade: u8,
+
+    /// Reload the game when its file changes on disk
+    pub watch: bool,
+    /// Save the preferences on exit (see [SavedState])
+    pub remember: bool,
+    /// Close the app when the program ends, instead of showing that it has ended
+    pub exit_on_halt: bool,
+    /// Print which unknown or unsupported instructions were ignored and how many times on exit,
+    /// handy to find out that the game needs another CHIP-8 variant
+    pub log_ignored: bool,
+    /// File the memory is written into on exit, for the inspection in a hex editor
+    pub dump_mem: Option<PathBuf>,
+    /// Labels of the game addresses, shown in the debug output
+    pub symbols: Symbols,
+    /// Keys of the app actions, Esc toggles pause too unless it leaves the fullscreen
+    pub bindings: Bindings,
+    /// Press CHIP-8 buttons by clicking on the window
+    pub mouse_keypad: bool,
+    /// Don't hide the cursor while the game is running
+    pub show_cursor: bool,
+    /// Off-screen position falls back to [WindowPos::Default]
+    /// Some platforms (for example, Wayland) don't let the apps place their windows at all
+    pub window_pos: WindowPos,
+    /// CHIP-8 button that is repeatedly pressed and released while held
+    pub turbo_btn: Option<u8>,
+    /// Auto-fire presses per second
+    pub turbo_rate: u8,
+    /// Press random buttons when the user hasn't touched anything for a while
+    pub attract: bool,
+
+    pub ff_audio: FastForwardAudio,
+    /// Min length of the biiip in frames, very short biiips may be hard to hear
+    pub min_beep: u8,
+    /// Silence the buzzer while the window is not focused
+    pub mute_on_unfocus: bool,
+    /// Pause the game while the window is not focused
+    pub pause_on_unfocus: bool,
+    /// Pause the game after so many seconds without any keyboard or mouse input,
+    /// the next press unpauses it
+    pub auto_pause: Option<u32>,
+    /// Warn that the quirks may be wrong if the game hasn't drawn anything for so many seconds
+    /// since it was loaded, the games that never draw usually run with the wrong quirks
+    pub blank_warning: Option<u32>,
+    /// Timers are frozen during the pause, this drops the sound that is left,
+    /// so it doesn't play after the pause
+    pub pause_clears_sound: bool,
 }
 impl Config {
-    pub fn new(cli: Cli) -> Self {
-        let palettes = cli.palettes.unwrap_or(DEFAULT_PALETTES.to_vec());
+    /// From the highest to the lowest precedence:
+    /// options from the command line, environment variables, the game settings and the saved state
+    pub fn new(cli: Cli, env: EnvConfig, rom: RomConfig, state: SavedState) -> Result<Self, ConfigError> {
+        let symbols = match &cli.symbols {
+            Some(path) => Symbols::load(path).map_err(ConfigError::Symbols)?,
+            None => Symbols::default(),
+        };
+
+        let mut palettes = cli.palettes
+            .or(rom.palettes)
+            .unwrap_or(DEFAULT_PALETTES.to_vec());
+        palettes.extend(cli.extra_palettes);
 
-        Self {
-            palette: palettes[0].clone(),
+        // Palettes are numbered from 1, just like in the window title
+        let start_palette = match cli.start_palette.or(env.start_palette) {
+            Some(n) if n == 0 || n > palettes.len() => {
+                return Err(ConfigError::NoSuchPalette(n, palettes.len()));
+            }
+            n => n.map(|n| n - 1),
+        };
+        let palette_index = start_palette
+            .or(state.palette_index.filter(|i| *i < palettes.len()))
+            .unwrap_or(0);
+
+        let speed = cli.speed.or(env.speed).or(rom.speed).or(state.speed).unwrap_or(DEFAULT_SPEED);
+
+        Ok(Self {
+            palette: palettes[palette_index].clone(),
             palettes,
-            cur_palette_index: 0,
+            cur_palette_index: palette_index,
+            multicolor: cli.multicolor,
+            colors: cli.colors,
+            ui_colors: cli.ui_colors,
 
-            speed: cli.speed.unwrap_or(DEFAULT_SPEED),
+            speed:
+                if cli.unsafe_speed { speed }
+                else { speed.min(MAX_SPEED) },
+            unsafe_speed: cli.unsafe_speed,
+            accurate_timing: cli.accurate_timing,
+            threaded: cli.threaded,
+            quirks: cli.quirks.or(rom.quirks).unwrap_or_default(),
+            strict: cli.strict,
+            seed: cli.seed.unwrap_or(DEFAULT_SEED),
+            entry: cli.entry,
 
-            draw_strategy: cli.draw_strategy
-        }
+            splash_frames: cli.splash_frames.unwrap_or(DEFAULT_SPLASH_FRAMES),
+            display_size: cli.display_size.unwrap_or((DISPLAY_WIDTH, DISPLAY_HEIGHT)),
+            ui_scale: cli.ui_scale.or(env.ui_scale).unwrap_or(1),
+            draw_strategy: cli.draw_strategy,
+            vsync: cli.vsync.unwrap_or(true),
+            backend: cli.backend,
+            software: cli.software,
+            smooth: cli.smooth,
+            fast_fade: cli.fast_fade,
+            afterglow: cli.afterglow || rom.afterglow.unwrap_or(false),
+            highlight_draws: cli.highlight_draws,
+            timer: cli.timer,
+            defer_clear: cli.defer_clear,
+            palette_fade: cli.palette_fade.unwrap_or(0),
+            cpu_meter: cli.cpu_meter,
+            grid: cli.grid,
+            grid_color: cli.grid_color.unwrap_or(Color(0, 0, 0)),
+            grid_opacity: cli.grid_opacity.unwrap_or(DEFAULT_GRID_OPACITY) as f32 / 100.0,
+
+            watch: cli.watch,
+            remember: cli.remember,
+            exit_on_halt: cli.exit_on_halt,
+            log_ignored: cli.log_ignored,
+            dump_mem: cli.dump_mem,
+            symbols,
+            bindings: cli.bindings,
+            mouse_keypad: cli.mouse_keypad,
+            show_cursor: cli.show_cursor,
+            window_pos: cli.window_pos,
+            turbo_btn: cli.turbo_btn,
+            turbo_rate: cli.turbo_rate.unwrap_or(DEFAULT_TURBO_RATE),
+            attract: cli.attract,
+
+            ff_audio: cli.ff_audio,
+            min_beep: cli.min_beep.unwrap_or(0),
+            mute_on_unfocus: cli.mute_on_unfocus.unwrap_or(true),
+            pause_on_unfocus: cli.pause_on_unfocus,
+            pause_clears_sound: cli.pause_clears_sound,
+            auto_pause: cli.auto_pause,
+            blank_warning: Some(cli.blank_warning.unwrap_or(DEFAULT_BLANK_WARNING)).filter(|s| *s > 0),
+        })
     }
 
     pub fn next_palette(&mut self) {
-        let new_index = (self.cur_palette_index + 1) % (self.palettes.len() - 1);
+        let new_index = (self.cur_palette_index + 1) % self.palettes.len();
 
         self.palette = self.palettes[new_index].clone();
         self.cur_palette_index = new_index;
@@ -144,11 +725,118 @@ impl Config {
         self.palette = self.palettes[new_index].clone();
         self.cur_palette_index = new_index;
     }
+    /// Jump to a random palette, it is never the current one
+    pub fn random_palette(&mut self, rng: &mut Rng) {
+        let len = self.palettes.len();
+        if len < 2 { return }
+
+        let offset = 1 + rng.below(len as u32 - 1) as usize;
+        let new_index = (self.cur_palette_index + offset) % len;
+
+        self.palette = self.palettes[new_index].clone();
+        self.cur_palette_index = new_index;
+    }
+
+    /// Color of the pixel that is on in the `planes` (bit mask, see [Config::colors])
+    pub fn plane_color(&self, planes: u8) -> Color {
+        self.palette_plane_color(&self.palette, planes)
+    }
+    /// Color of the UI text (`on`) or box, it always stands out from the game (see [UiColors])
+    pub fn ui_color(&self, on: bool) -> Color {
+        let contrast = |c: &Color| if c.luminance() < 0.5 { Color::WHITE } else { Color::BLACK };
 
-    pub fn fg(&self) -> &Color {
-        &self.palette.0
+        match &self.ui_colors {
+            UiColors::Auto => match &self.palette.ui {
+                Some(ui) => if on { contrast(ui) } else { ui.clone() },
+                None => {
+                    let bg = self.plane_color(0);
+                    let text = contrast(&bg);
+
+                    if on { text }
+                    else { bg.mix_linear(&text, UI_BOX_CONTRAST) }
+                }
+            },
+            UiColors::Palette => self.plane_color(u8::from(on)),
+            UiColors::Custom(fg, bg) => if on { fg.clone() } else { bg.clone() },
+        }
     }
-    pub fn bg(&self) -> &Color {
-        &self.palette.1
+    /// Same as [Config::plane_color], but for the colors of the `palette` instead of the current one
+    pub fn palette_plane_color(&self, palette: &Palette, planes: u8) -> Color {
+        let Palette { fg, bg, .. } = palette;
+        if !self.multicolor {
+            return if planes == 0 { bg.clone() } else { fg.clone() };
+        }
+
+        match &self.colors {
+            Some(colors) => colors[(planes & 0b11) as usize].clone(),
+            // Second plane is dimmer, so it is distinguishable from the first one
+            None => match planes & 0b11 {
+                0 => bg.clone(),
+                1 => fg.clone(),
+                2 => bg.mix(fg, 0.5),
+                _ => bg.mix(fg, 0.75),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(cli: Cli) -> Config {
+        Config::new(cli, EnvConfig::default(), RomConfig::default(), SavedState::default()).unwrap()
+    }
+
+    #[test]
+    fn next_palette_reaches_added_palettes() {
+        let extra = Palette::new(Color(1, 2, 3), Color(4, 5, 6));
+        let mut config = config_with(Cli {
+            extra_palettes: vec![extra.clone()],
+            ..Cli::default()
+        });
+
+        for _ in 0..DEFAULT_PALETTES.len() {
+            config.next_palette();
+        }
+        assert_eq!(config.palette, extra);
+
+        config.next_palette();
+        assert_eq!(config.cur_palette_index, 0);
+    }
+    #[test]
+    fn next_palette_after_start_palette() {
+        let palettes = vec![
+            Palette::new(Color(1, 1, 1), Color(0, 0, 0)),
+            Palette::new(Color(2, 2, 2), Color(0, 0, 0)),
+            Palette::new(Color(3, 3, 3), Color(0, 0, 0)),
+        ];
+        let mut config = config_with(Cli {
+            palettes: Some(palettes.clone()),
+            start_palette: Some(3),
+            ..Cli::default()
+        });
+        assert_eq!(config.palette, palettes[2]);
+
+        // Cycling forward from the last palette comes back to it
+        for palette in palettes.iter().cycle().take(4) {
+            config.next_palette();
+            assert_eq!(&config.palette, palette);
+        }
+        config.next_palette();
+        config.next_palette();
+        assert_eq!(config.palette, palettes[2]);
+    }
+    #[test]
+    fn next_palette_with_single_palette() {
+        let only = Palette::new(Color(1, 2, 3), Color(4, 5, 6));
+        let mut config = config_with(Cli {
+            palettes: Some(vec![only.clone()]),
+            ..Cli::default()
+        });
+
+        config.next_palette();
+        config.prev_palette();
+        assert_eq!(config.palette, only);
     }
 }
@@ -2,30 +2,136 @@
 //! - http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
 //! - https://tobiasvl.github.io/blog/write-a-chip-8-emulator
 //! - https://www.freecodecamp.org/news/creating-your-very-own-chip-8-emulator
-//!
-//! TODO:
-//! - handle program end
 
-use crate::font::{CHIP_FONT, CHIP_FONT_LEN};
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    font::{CHIP_FONT, CHIP_FONT_LEN},
+    quirks::{self, Quirks},
+    rng::{Rng, DEFAULT_SEED},
+};
 
 /// CHIP-8 display width
 pub const DISPLAY_WIDTH: u32 = 64;
 /// CHIP-8 display height
 pub const DISPLAY_HEIGHT: u32 = 32;
-/// Length of the display 1D array
-pub const DISPLAY_DATA_LEN: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize;
+/// Min width and height of a non-standard display (see [Cpu::with_display])
+pub const MIN_DISPLAY_SIZE: u32 = 8;
+/// Max width and height of a non-standard display, UI coordinates must fit in a byte
+pub const MAX_DISPLAY_SIZE: u32 = 255;
 /// Max memory size
-const MEMORY_CAPACITY: usize = 4096;
+pub const MEMORY_CAPACITY: usize = 4096;
 /// Max stack size
 const STACK_CAPACITY: usize = 16;
 
 /// Starting address of the program in the memory
 const START_PC: u16 = 0x200;
+/// Max size of the game that fits in the memory
+pub const MAX_ROM_SIZE: usize = MEMORY_CAPACITY - START_PC as usize;
+
+// Errors
+#[derive(Debug)]
+pub enum CpuError {
+    AddrOutOfRange(u16),
+    NoSuchRegister(u8),
+}
+impl Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddrOutOfRange(a) => write!(f, "Address {a:#05X} is out of memory"),
+            Self::NoSuchRegister(x) => write!(f, "No such register V{x:X}"),
+        }
+    }
+}
+/// Errors of the executed instructions, returned only in strict mode
+#[derive(Debug)]
+pub enum ExecError {
+    /// `0NNN` - call of the native RCA 1802 routine at NNN
+    UnsupportedMachineCall(u16),
+    /// `Dxyn` - sprite at I doesn't fit in the memory
+    SpriteOutOfMemory(u16),
+    /// `Fx33`, `Fx55`, `Fx65`, `5xy2` or `5xy3` - bytes at I don't fit in the memory
+    IndexOutOfMemory(u16),
+    /// Instruction at PC doesn't fit in the memory, the program has run off its end
+    PcOutOfMemory(u16),
+    /// The instruction doesn't exist
+    UnknownInstruction(u16),
+}
+impl Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedMachineCall(a) => write!(f, "Machine code routine call at {a:#05X} is not supported"),
+            Self::SpriteOutOfMemory(i) => write!(f, "Sprite at {i:#05X} doesn't fit in the memory"),
+            Self::IndexOutOfMemory(i) => write!(f, "Memory at I={i:#05X} is out of range"),
+            Self::PcOutOfMemory(pc) => write!(f, "Program has run off the end of the memory at {pc:#05X}"),
+            Self::UnknownInstruction(ins) => write!(f, "Unknown instruction {ins:#06X}"),
+        }
+    }
+}
+
+/// What happened during [Cpu::step]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    /// The display was drawn on, cleared or scrolled
+    pub display_changed: bool,
+    /// The program waits for a button press
+    pub waiting: bool,
+    /// The program has ended (see [Cpu::is_halted])
+    pub halted: bool,
+    /// The buzzer should be playing
+    pub sound: bool,
+}
+
+/// Rectangular region of the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+impl Rect {
+    /// The whole display of the given size
+    pub fn full(width: u32, height: u32) -> Self {
+        Self { x: 0, y: 0, w: width, h: height }
+    }
+
+    /// Rect of a single pixel
+    pub fn pixel(x: u32, y: u32) -> Self {
+        Self { x, y, w: 1, h: 1 }
+    }
+
+    /// Smallest rect containing both rects
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w).max(other.x + other.w);
+        let bottom = (self.y + self.h).max(other.y + other.h);
+
+        Self { x, y, w: right - x, h: bottom - y }
+    }
+    /// Same as [Rect::union], but any of the rects may be missing
+    pub fn merge(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b)
+        }
+    }
+}
 
 /// CHIP-8 cpu
+#[derive(Clone)]
 pub struct Cpu {
     /// Whether the game is loaded
     ready: bool,
+    /// Interpreter behaviour the game expects
+    pub quirks: Quirks,
+    /// Warn about suspicious behaviour of the game, useful for debugging it
+    pub strict: bool,
+    /// Original bytes of the loaded game, used to restart it cleanly
+    rom: Vec<u8>,
+    /// Address the execution starts from, kept across restarts
+    entry: u16,
 
     /// V*x* registers - where *x* is a hex digit from `0x0` through `0xF`
     v: [u8; 16],
@@ -39,19 +145,39 @@ pub struct Cpu {
     /// DT register - delay timer
     dt: u8,
     /// ST register - sound timer
-    pub st: u8,
+    st: u8,
 
     /// List of adresses to which the interpreter should return after finishing with a subroutine
     stack: [u16; STACK_CAPACITY],
     memory: [u8; MEMORY_CAPACITY],
-    /// 1D array of each pixel state (on/off)
-    pub display: [bool; DISPLAY_DATA_LEN],
+    /// Display size in pixels
+    width: u32,
+    height: u32,
+    /// 1D array of each pixel state (on/off), row by row
+    pub display: Vec<bool>,
 
-    /// Increases by 1 on every step. Mostly used for random number generation
-    tick: u16,
+    /// Seed of the random numbers, the same seed always gives the same `Cxkk` results
+    seed: u32,
+    rng: Rng,
     /// Whether to increase the program counter by 2 or not
     jump_next: bool,
-    pub display_changed: bool,
+    /// Whether the program has ended (see [Cpu::is_halted])
+    halted: bool,
+    /// Whether the display was changed by the current step
+    display_changed: bool,
+    /// Region of the display changed since the last [Cpu::take_dirty] call
+    dirty: Option<Rect>,
+    /// Bounds of the last drawn sprite on the display since the last [Cpu::take_last_draw] call
+    last_draw: Option<Rect>,
+    /// Whether the display was cleared and nothing was drawn after that
+    clear_pending: bool,
+
+    /// Whether the memory at each address was written by the font, the game or a store instruction
+    initialized: [bool; MEMORY_CAPACITY],
+    /// Whether a strict mode warning was already printed for each address
+    warned: [bool; MEMORY_CAPACITY],
+    /// How many times each ignored instruction was executed, kept across restarts
+    ignored: BTreeMap<u16, u32>,
 
     /// Whether is waiting for a button press for Vx
     waiting_button_for: Option<u8>,
@@ -59,37 +185,165 @@ pub struct Cpu {
     buttons: [bool; 16],
 }
 impl Cpu {
+    /// CPU with a display of non-standard size, for the variants of CHIP-8 that had them
+    /// Size must be from [MIN_DISPLAY_SIZE] to [MAX_DISPLAY_SIZE]
+    pub fn with_display(width: u32, height: u32) -> Self {
+        let mut memory = [0u8; MEMORY_CAPACITY];
+        let mut initialized = [false; MEMORY_CAPACITY];
+
+        // Store the font into the memory from 0x0 to font_length
+        memory[..CHIP_FONT_LEN].copy_from_slice(&CHIP_FONT);
+        initialized[..CHIP_FONT_LEN].fill(true);
+
+        Self {
+            ready: false,
+            quirks: Quirks::default(),
+            strict: false,
+            rom: Vec::new(),
+            entry: START_PC,
+
+            v: [0; 16],
+            i: 0,
+            pc: START_PC,
+            sp: 0,
+
+            dt: 0,
+            st: 0,
+
+            stack: [0; STACK_CAPACITY],
+            memory,
+            width,
+            height,
+            display: vec![false; (width * height) as usize],
+
+            seed: DEFAULT_SEED,
+            rng: Rng::new(DEFAULT_SEED),
+            jump_next: true,
+            halted: false,
+            display_changed: false,
+            // Nothing was drawn yet, but the whole display is new
+            dirty: Some(Rect::full(width, height)),
+            last_draw: None,
+            clear_pending: false,
+
+            initialized,
+            warned: [false; MEMORY_CAPACITY],
+            ignored: BTreeMap::new(),
+
+            buttons: [false; 16],
+            waiting_button_for: None,
+        }
+    }
+
     /// Load a game from binary
     pub fn load(&mut self, bytes: &[u8]) {
         let start = START_PC as usize;
 
         // Store the game into the memory from 0x200 to 0x200 + game_length
         self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        self.initialized[start..start + bytes.len()].fill(true);
+        self.rom = bytes.to_vec();
+        crate::debug!("Loaded the game: {} bytes", bytes.len());
 
         self.ready = true;
     }
-    /// Reset everything
+    /// Reset everything except the quirks and strict mode
     pub fn unload(&mut self) {
-        *self = Self::default();
+        *self = Self {
+            quirks: self.quirks,
+            strict: self.strict,
+            entry: self.entry,
+            pc: self.entry,
+            seed: self.seed,
+            rng: Rng::new(self.seed),
+            ..Self::with_display(self.width, self.height)
+        };
         self.ready = false;
     }
-    /// Reset CPU state, but leave memory untouched
+    /// Reset CPU state, but leave memory, the game, quirks, strict mode and seed untouched
+    /// Random numbers start over, so the restarted game gets the same numbers
+    /// Keep in mind that the game may have modified itself during the previous run
     pub fn restart(&mut self) {
         *self = Self {
+            ready: self.ready,
+            quirks: self.quirks,
+            strict: self.strict,
+            seed: self.seed,
+            rng: Rng::new(self.seed),
+            rom: std::mem::take(&mut self.rom),
+            entry: self.entry,
+            pc: self.entry,
             memory: self.memory,
-            ..Default::default()
+            initialized: self.initialized,
+            warned: self.warned,
+            ignored: std::mem::take(&mut self.ignored),
+            ..Self::with_display(self.width, self.height)
         }
     }
+    /// Reset CPU state and restore the memory to the pristine state of the loaded game
+    pub fn reload(&mut self) {
+        self.restart();
+
+        // Wipe everything the previous run has written, font is never modified by games
+        self.memory[START_PC as usize..].fill(0);
+        self.initialized[START_PC as usize..].fill(false);
+        let rom = std::mem::take(&mut self.rom);
+        self.load(&rom);
+    }
 
-    /// Returns whether the cpu updated or not
-    pub fn step(&mut self) {
-        // Step only if it is not waiting for a button press
-        if self.waiting_button_for.is_some() {
-            return;
+    /// Execute the next instruction and tell what has happened
+    /// Errors are returned only in strict mode, the program is halted after the error
+    pub fn step(&mut self) -> Result<StepResult, ExecError> {
+        self.display_changed = false;
+
+        // Step only if the game is loaded, it is not waiting for a button press
+        // and the program hasn't ended
+        if !self.ready || self.waiting_button_for.is_some() || self.halted {
+            return Ok(self.step_result());
+        }
+
+        // Program that runs off the end of the memory continues from its start
+        if self.pc as usize + 1 >= MEMORY_CAPACITY {
+            if self.strict {
+                self.halted = true;
+                return Err(ExecError::PcOutOfMemory(self.pc));
+            }
+            self.pc &= 0xFFF;
         }
         let pc = self.pc as usize;
 
-        self.tick = self.tick.wrapping_add(1);
+        let ins = self.next_instruction();
+        self.check_execute(pc);
+
+        if let Err(e) = self.execute(ins) {
+            self.halted = true;
+            return Err(e);
+        }
+
+        if self.jump_next {
+            // Increase by 2 because each instruction consists of 2 bytes
+            self.pc += 2;
+        }
+        self.jump_next = true;
+
+        Ok(self.step_result())
+    }
+    fn step_result(&self) -> StepResult {
+        StepResult {
+            display_changed: self.display_changed,
+            waiting: self.waiting_button_for.is_some(),
+            halted: self.halted,
+            sound: self.st > 0,
+        }
+    }
+    /// Whether a game is loaded, otherwise [Cpu::step] does nothing
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+    /// Returns the instruction that will be executed on the next step
+    /// The instruction at the very end of the memory continues at its start
+    pub fn next_instruction(&self) -> u16 {
+        let pc = self.pc as usize % MEMORY_CAPACITY;
 
         // We need to shift the first byte by 8 bits left so we can "concatenate"
         // it with the second byte
@@ -97,16 +351,104 @@ impl Cpu {
         // For example:
         // 0xAB << 8 -> 0xAB00
         // 0xAB00 | 0x12 -> 0xAB12
-        let ins = ((self.memory[pc] as u16) << 8) | self.memory[pc + 1] as u16;
+        ((self.memory[pc] as u16) << 8) | self.memory[(pc + 1) % MEMORY_CAPACITY] as u16
+    }
+    /// Returns whether the program has ended
+    /// Many programs end by jumping to the same instruction forever, such jumps and
+    /// SUPER-CHIP `00FD` (exit) instruction are treated as the end of the program
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
-        self.execute(ins);
+    /// The whole memory, including the font and everything the game has written
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+    /// How many times each unknown or unsupported instruction was skipped, sorted by the instruction
+    pub fn ignored_instructions(&self) -> &BTreeMap<u16, u32> {
+        &self.ignored
+    }
+    /// Size of the loaded game in bytes
+    pub fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+    /// Display width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    /// Display height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 
-        if self.jump_next {
-            // Increase by 2 because each instruction consists of 2 bytes
-            self.pc += 2;
-        }
-        self.jump_next = true;
+    /// Returns the bit mask of the display planes the pixel at `index` is on in
+    /// There is only the first plane for now
+    pub fn pixel_planes(&self, index: usize) -> u8 {
+        u8::from(self.display[index])
+    }
+
+    /// Clear the display without touching anything else, handy to see what the game redraws
+    /// Unlike `00E0` it is never deferred (see [Cpu::is_clear_pending])
+    pub fn clear_display(&mut self) {
+        let pending = self.clear_pending;
+        self.clear();
+        self.clear_pending = pending;
+    }
+
+    /// Returns whether the display was cleared and nothing was drawn after that
+    /// Games that clear and redraw the whole screen every frame may show the empty screen
+    /// between these two, so the renderer may want to wait a bit
+    pub fn is_clear_pending(&self) -> bool {
+        self.clear_pending
     }
+
+    /// Returns the region of the display changed since the last call
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+    /// Bounds of the last sprite drawn since the previous call, the wrapped part is not included
+    pub fn take_last_draw(&mut self) -> Option<Rect> {
+        self.last_draw.take()
+    }
+
+    /// Returns the currently executing address
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+    /// Returns the value of the I register
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+    /// Returns the stack pointer, the number of the nested subroutine calls
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Returns the value of the delay timer (DT register)
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+    /// Returns the value of the sound timer (ST register)
+    /// The buzzer should be playing while it is above zero
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.dt = value;
+    }
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.st = value;
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+    /// Start the random numbers over from the `seed`, it is kept across restarts
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
     pub fn step_timers(&mut self) {
         // Decrement times
         self.dt = self.dt.saturating_sub(1);
@@ -125,12 +467,23 @@ impl Cpu {
         self.buttons[btn as usize] = false;
     }
 
-    fn is_btn_pressed(&mut self, btn: u8) -> bool {
+    /// Release every button and cancel the wait for a button press
+    /// Canceled `Fx0A` is executed again, so the game waits for a fresh press instead of
+    /// getting a button that was held before
+    pub fn release_all_buttons(&mut self) {
+        self.buttons = [false; 16];
+
+        if self.waiting_button_for.take().is_some() {
+            self.pc -= 2;
+        }
+    }
+    /// Whether the CHIP-8 button is held, any number of them can be held at once
+    pub fn is_btn_pressed(&self, btn: u8) -> bool {
         self.buttons[btn as usize]
     }
 
     /// Execute an instruction
-    fn execute(&mut self, ins: u16) {
+    fn execute(&mut self, ins: u16) -> Result<(), ExecError> {
         let a = (ins & 0xF000) >> 12;
         let b = (ins & 0x0F00) >> 8;
         let c = (ins & 0x00F0) >> 4;
@@ -150,13 +503,18 @@ impl Cpu {
         match (a, b, c, d) {
             // Clear the display
             (0, 0, 0xE, 0) => self.clear(),
+            // Scroll the display up by N rows (XO-CHIP 00DN and MegaChip 00BN)
+            (0, 0, 0xD | 0xB, _) => self.scroll_up(nibble),
             // Draw a N-byte sprite at Vx and Vy
-            (0xD, _, _, _) => self.draw(x, y, nibble),
+            (0xD, _, _, _) => self.draw(x, y, nibble)?,
+
+            // Exit the program (SUPER-CHIP)
+            (0, 0, 0xF, 0xD) => self.halted = true,
 
             // Jump to NNN
-            (0x1, _, _, _) => self.jump(addr),
-            // Jump to NNN + V0
-            (0xB, _, _, _) => self.jump(addr + self.get(0) as u16),
+            (0x1, _, _, _) => self.jump_or_halt(addr),
+            // Jump to NNN + V0 (or XNN + Vx)
+            (0xB, _, _, _) => self.jump_with_offset(x, addr),
             // Jump to a subroutine
             (0x2, _, _, _) => self.call(addr),
             // Return from a subroutine
@@ -167,7 +525,7 @@ impl Cpu {
             // Skip if Vx != KK
             (0x4, _, _, _) => self.skip_vx_neq_byte(x, byte),
             // Skip if Vx == Vy
-            (0x5, _, _, _) => self.skip_vx_eq_vy(x, y),
+            (0x5, _, _, 0) => self.skip_vx_eq_vy(x, y),
             // Skip if Vx != Vy
             (0x9, _, _, 0) => self.skip_vx_neq_vy(x, y),
 
@@ -190,14 +548,14 @@ impl Cpu {
             // Vx = Vx ^ Vy
             (0x8, _, _, 3) => self.xor(x, y),
             // Vx = Vx >> 1
-            (0x8, _, _, 6) => self.shift_right(x),
+            (0x8, _, _, 6) => self.shift_right(x, y),
             // Vx = Vx << 1
-            (0x8, _, _, 0xE) => self.shift_left(x),
+            (0x8, _, _, 0xE) => self.shift_left(x, y),
             // Vx = random_number & KK
             (0xC, _, _, _) => self.rand(x, byte),
 
             // Vx = DT
-            (0xF, _, 0, 0x7) => { self.set(x, self.dt); },
+            (0xF, _, 0, 0x7) => { self.set(x, self.delay_timer()); },
             // DT = Vx
             (0xF, _, 0x1, 0x5) => self.dt = self.get(x),
             // ST = Vx
@@ -218,15 +576,26 @@ impl Cpu {
             (0xF, _, 0, 0xA) => self.wait_for_keypress(x),
 
             // Store BCD of Vx
-            (0xF, _, 0x3, 0x3) => self.store_bcd(x),
+            (0xF, _, 0x3, 0x3) => self.store_bcd(x)?,
             // Store V0 through Vx to memory starting from I
-            (0xF, _, 0x5, 0x5) => self.store_through(x),
+            (0xF, _, 0x5, 0x5) => self.store_through(x)?,
             // Read to V0 through Vx from memory starting from I
-            (0xF, _, 0x6, 0x5) => self.read_through(x),
+            (0xF, _, 0x6, 0x5) => self.read_through(x)?,
+            // Store Vx through Vy to memory starting from I (XO-CHIP)
+            (0x5, _, _, 2) => self.store_range(x, y)?,
+            // Read to Vx through Vy from memory starting from I (XO-CHIP)
+            (0x5, _, _, 3) => self.read_range(x, y)?,
 
-            (0, _, _, _) => (/* ignore "jump to sys addr" */),
-            _ => (/* ignore unknown instructions for now */)
+            // Unimplemented SUPER-CHIP instructions are not machine code calls
+            (0, _, _, _) if self.strict && !quirks::is_schip_instruction(ins) => {
+                return Err(ExecError::UnsupportedMachineCall(addr));
+            }
+            (0, _, _, _) => self.ignore(ins) /* "jump to sys addr" */,
+            _ if self.strict => return Err(ExecError::UnknownInstruction(ins)),
+            _ => self.ignore(ins) /* unknown instructions for now */
         }
+
+        Ok(())
     }
 
     /// Get register Vx
@@ -239,25 +608,120 @@ impl Cpu {
         value
     }
 
+    /// Write a byte to the memory at `addr`
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<(), CpuError> {
+        let byte = self.memory
+            .get_mut(addr as usize)
+            .ok_or(CpuError::AddrOutOfRange(addr))?;
+
+        *byte = value;
+        self.initialized[addr as usize] = true;
+        Ok(())
+    }
+    /// Start the execution from `addr` instead of 0x200, now and after every restart
+    /// The whole instruction at `addr` must be in the memory
+    pub fn set_entry(&mut self, addr: u16) -> Result<(), CpuError> {
+        if addr as usize + 1 >= MEMORY_CAPACITY {
+            return Err(CpuError::AddrOutOfRange(addr));
+        }
+
+        self.entry = addr;
+        self.pc = addr;
+        Ok(())
+    }
+    /// Same as [Cpu::set], but checks whether register Vx exists
+    pub fn set_register(&mut self, x: u8, value: u8) -> Result<(), CpuError> {
+        if x as usize >= self.v.len() {
+            return Err(CpuError::NoSuchRegister(x));
+        }
+
+        self.set(x, value);
+        Ok(())
+    }
+
+    // Strict mode
+    /// Warn if the game executes the reserved memory (below 0x200) or the memory it never wrote
+    fn check_execute(&mut self, addr: usize) {
+        if !self.strict { return }
+
+        if addr < START_PC as usize {
+            self.warn_once(addr, "executing the reserved memory");
+        } else if !self.initialized[addr] {
+            self.warn_once(addr, "executing the uninitialized memory");
+        }
+    }
+    /// In strict mode, fail if `len` bytes at I don't fit in the memory,
+    /// otherwise they wrap around to its start
+    fn check_index_range(&self, len: usize) -> Result<(), ExecError> {
+        if self.strict && self.i as usize + len > MEMORY_CAPACITY {
+            return Err(ExecError::IndexOutOfMemory(self.i));
+        }
+        Ok(())
+    }
+    /// Warn if the game reads the memory it never wrote
+    fn check_read(&mut self, addr: usize) {
+        if !self.strict { return }
+
+        if self.initialized.get(addr) == Some(&false) {
+            self.warn_once(addr, "reading the uninitialized memory");
+        }
+    }
+    /// Count the instruction that did nothing (see [Cpu::ignored_instructions])
+    fn ignore(&mut self, ins: u16) {
+        *self.ignored.entry(ins).or_default() += 1;
+    }
+    /// Print the warning, but only once for each address
+    fn warn_once(&mut self, addr: usize, what: &str) {
+        if self.warned[addr] { return }
+        self.warned[addr] = true;
+
+        eprintln!("Strict: {} at {:#05X} (PC {:#05X})", what, addr, self.pc);
+    }
+
     // Instructions
     fn clear(&mut self) {
         self.display.fill(false);
         self.display_changed = true;
+        self.dirty = Some(Rect::full(self.width, self.height));
+        self.clear_pending = true;
+    }
+    /// Move the display contents up by `n` rows, the bottom rows become empty
+    /// The display is always in low resolution, so `n` is always in display rows
+    fn scroll_up(&mut self, n: u8) {
+        let sw = self.width as usize;
+        let len = self.display.len();
+        let shift = (n as usize * sw).min(len);
+
+        self.display.copy_within(shift.., 0);
+        self.display[len - shift..].fill(false);
+        self.display_changed = true;
+        self.dirty = Some(Rect::full(self.width, self.height));
     }
-    fn draw(&mut self, x: u8, y: u8, n: u8) {
-        let vx = self.get(x) as usize;
-        let vy = self.get(y) as usize;
-        let sw = DISPLAY_WIDTH as usize;
-        let sh = DISPLAY_HEIGHT as usize;
+    /// Sprite rows past the end of the memory are read from its start
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), ExecError> {
+        if self.strict && self.i as usize + n as usize > MEMORY_CAPACITY {
+            return Err(ExecError::SpriteOutOfMemory(self.i));
+        }
+
+        let sw = self.width as usize;
+        let sh = self.height as usize;
+        // The starting position always wraps around
+        let vx = self.get(x) as usize % sw;
+        let vy = self.get(y) as usize % sh;
 
         let mut overlaps = false;
+        let mut dirty = self.dirty;
 
         for row in 0..n as usize {
-            let mut sprite = self.memory[self.i as usize + row];
+            let addr = (self.i as usize + row) % MEMORY_CAPACITY;
+            self.check_read(addr);
+            let mut sprite = self.memory[addr];
 
             for col in 0..8 {
+                let clipped = vx + col >= sw || vy + row >= sh;
+
                 // Check if a pixel exists in the sprite or not
-                if sprite & 0x80 != 0 {
+                if sprite & 0x80 != 0 && !(clipped && self.quirks.clip_sprites) {
                     let x = (vx + col) % sw;
                     let y = (vy + row) % sh;
                     let idx = y * sw + x;
@@ -269,6 +733,8 @@ impl Cpu {
                     } else {
                         self.display[idx] = true;
                     }
+
+                    dirty = Rect::merge(dirty, Some(Rect::pixel(x as u32, y as u32)));
                 }
 
                 // Shift the sprite's pixels left so we can get next pixel in the sprite
@@ -276,14 +742,47 @@ impl Cpu {
             }
         }
 
+        // Empty sprites have nothing to show
+        if n > 0 {
+            self.last_draw = Some(Rect {
+                x: vx as u32,
+                y: vy as u32,
+                w: 8.min(sw - vx) as u32,
+                h: (n as usize).min(sh - vy) as u32,
+            });
+        }
+
         self.set(0xF, u8::from(overlaps));
         self.display_changed = true;
+        self.dirty = dirty;
+        self.clear_pending = false;
+
+        Ok(())
     }
 
     fn jump(&mut self, addr: u16) {
         self.pc = addr;
         self.jump_next = false;
     }
+    /// Jump to `addr` + V0, or to `addr` + Vx when [Quirks::jump_offset_uses_vx] is enabled
+    /// Target past the end of the memory wraps around to its start
+    fn jump_with_offset(&mut self, x: u8, addr: u16) {
+        let offset = if self.quirks.jump_offset_uses_vx {
+            self.get(x)
+        } else {
+            self.get(0)
+        };
+
+        self.jump((addr + offset as u16) & 0xFFF);
+    }
+    /// Same as [Cpu::jump], but jumping to the same instruction ends the program
+    fn jump_or_halt(&mut self, addr: u16) {
+        if addr == self.pc {
+            self.halted = true;
+        }
+
+        self.jump(addr)
+    }
     fn call(&mut self, addr: u16) {
         // Store current program counter into the stack and increase stack pointer
         self.stack[self.sp as usize] = self.pc;
@@ -344,30 +843,54 @@ impl Cpu {
     }
     fn or(&mut self, x: u8, y: u8) {
         self.set(x, self.get(x) | self.get(y));
+        self.logic_vf_reset();
     }
     fn and(&mut self, x: u8, y: u8) {
         self.set(x, self.get(x) & self.get(y));
+        self.logic_vf_reset();
     }
     fn xor(&mut self, x: u8, y: u8) {
         self.set(x, self.get(x) ^ self.get(y));
+        self.logic_vf_reset();
+    }
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.set(0xF, 0);
+        }
     }
-    fn shift_right(&mut self, x: u8) {
+    fn shift_right(&mut self, x: u8, y: u8) {
+        if self.quirks.shift_uses_vy {
+            self.set_vx_vy(x, y);
+        }
+
         self.set(0xF, self.get(x) & 0x1);
         self.v[x as usize] >>= 1;
     }
-    fn shift_left(&mut self, x: u8) {
+    fn shift_left(&mut self, x: u8, y: u8) {
+        if self.quirks.shift_uses_vy {
+            self.set_vx_vy(x, y);
+        }
+
         self.set(0xF, self.get(x) & 0x80);
         self.v[x as usize] <<= 1;
     }
     fn rand(&mut self, x: u8, byte: u8) {
-        // "Generates" pseudo random number 0..=255 based on tick
-        // Jump a bunch of random math and we have a random number!
-        let num = (((self.tick as f32 * 99999.0).sin() + 1.0) / 2.0 * 255.0) as u8;
+        let num = self.rng.below(256) as u8;
         self.set(x, num & byte);
     }
 
+    /// Add Vx to I, with [Quirks::index_overflow_sets_vf] I is kept in 12 bits
+    /// Otherwise I keeps all 16 bits, the memory past 0xFFF wraps around to its start
+    /// (or fails in strict mode)
     fn add_i_vx(&mut self, x: u8) {
-        self.i = self.i.wrapping_add(self.get(x) as u16);
+        let i = self.i.wrapping_add(self.get(x) as u16);
+
+        if self.quirks.index_overflow_sets_vf {
+            self.i = i & 0xFFF;
+            self.set(0xF, u8::from(i > 0xFFF));
+        } else {
+            self.i = i;
+        }
     }
     fn set_i_sprite(&mut self, x: u8) {
         self.i = self.get(x) as u16 * 5;
@@ -395,55 +918,350 @@ impl Cpu {
     /// I = 2
     /// I+1 = 3
     /// I+2 = 0
-    fn store_bcd(&mut self, x: u8) {
+    /// Digits past the end of the memory are stored at its start
+    fn store_bcd(&mut self, x: u8) -> Result<(), ExecError> {
+        self.check_index_range(3)?;
         let vx = self.get(x);
-        let i = self.i as usize;
+        let digits = [vx / 100, (vx % 100) / 10, vx % 10];
 
-        self.memory[i] = vx / 100; // Hundreds
-        self.memory[i + 1] = (vx % 100) / 10; // Tens
-        self.memory[i + 2] = vx % 10; // Ones
+        for (offset, digit) in digits.into_iter().enumerate() {
+            let addr = (self.i as usize + offset) % MEMORY_CAPACITY;
+            self.memory[addr] = digit;
+            self.initialized[addr] = true;
+        }
+        Ok(())
     }
     /// Store registers V0 through Vx in memory starting from I
-    fn store_through(&mut self, x: u8) {
+    /// Registers past the end of the memory are stored at its start
+    fn store_through(&mut self, x: u8) -> Result<(), ExecError> {
+        self.check_index_range(x as usize + 1)?;
         for xx in 0..=x {
-            self.memory[self.i as usize + xx as usize] = self.get(xx);
+            let addr = (self.i as usize + xx as usize) % MEMORY_CAPACITY;
+            self.memory[addr] = self.get(xx);
+            self.initialized[addr] = true;
         }
+
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+        Ok(())
     }
     /// Read in registers V0 through Vx from memory starting from I
-    fn read_through(&mut self, x: u8) {
+    /// Registers past the end of the memory are read from its start
+    fn read_through(&mut self, x: u8) -> Result<(), ExecError> {
+        self.check_index_range(x as usize + 1)?;
         for xx in 0..=x {
-            self.set(xx, self.memory[self.i as usize + xx as usize]);
+            let addr = (self.i as usize + xx as usize) % MEMORY_CAPACITY;
+            self.check_read(addr);
+            self.set(xx, self.memory[addr]);
+        }
+
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
         }
+        Ok(())
+    }
+    /// Registers from Vx to Vy, in the descending order if `x` > `y`
+    fn register_range(x: u8, y: u8) -> Vec<u8> {
+        if x <= y { (x..=y).collect() }
+        else { (y..=x).rev().collect() }
+    }
+    /// Store registers Vx through Vy in memory starting from I, I is left untouched
+    fn store_range(&mut self, x: u8, y: u8) -> Result<(), ExecError> {
+        let regs = Self::register_range(x, y);
+        self.check_index_range(regs.len())?;
+
+        for (offset, xx) in regs.into_iter().enumerate() {
+            let addr = (self.i as usize + offset) % MEMORY_CAPACITY;
+            self.memory[addr] = self.get(xx);
+            self.initialized[addr] = true;
+        }
+        Ok(())
+    }
+    /// Read in registers Vx through Vy from memory starting from I, I is left untouched
+    fn read_range(&mut self, x: u8, y: u8) -> Result<(), ExecError> {
+        let regs = Self::register_range(x, y);
+        self.check_index_range(regs.len())?;
+
+        for (offset, xx) in regs.into_iter().enumerate() {
+            let addr = (self.i as usize + offset) % MEMORY_CAPACITY;
+            self.check_read(addr);
+            self.set(xx, self.memory[addr]);
+        }
+        Ok(())
     }
 }
 impl Default for Cpu {
     fn default() -> Self {
-        let mut memory = [0u8; MEMORY_CAPACITY];
+        Self::with_display(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+}
 
-        // Store the font into the memory from 0x0 to font_length
-        memory[..CHIP_FONT_LEN].copy_from_slice(&CHIP_FONT);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Self {
-            ready: false,
+    /// CPU with the `program` loaded at 0x200
+    fn cpu_with(program: &[u8]) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load(program);
+        cpu
+    }
 
-            v: [0; 16],
-            i: 0,
-            pc: START_PC,
-            sp: 0,
+    #[test]
+    fn jump_with_offset_uses_v0() {
+        // B300
+        let mut cpu = cpu_with(&[0xB3, 0x00]);
+        cpu.set(0, 0x10);
+        cpu.set(3, 0x20);
 
-            dt: 0,
-            st: 0,
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x310);
+    }
+    #[test]
+    fn jump_with_offset_uses_vx() {
+        // B320 jumps to 0x320 + V3
+        let mut cpu = cpu_with(&[0xB3, 0x20]);
+        cpu.quirks.jump_offset_uses_vx = true;
+        cpu.set(0, 0x10);
+        cpu.set(3, 0x05);
 
-            stack: [0; STACK_CAPACITY],
-            memory,
-            display: [false; DISPLAY_DATA_LEN],
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x325);
+    }
+    #[test]
+    fn jump_with_offset_wraps() {
+        // BFFF
+        let mut cpu = cpu_with(&[0xBF, 0xFF]);
+        cpu.set(0, 0xFF);
 
-            tick: 0,
-            jump_next: true,
-            display_changed: false,
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0FE);
+        cpu.step().unwrap();
+    }
 
-            buttons: [false; 16],
-            waiting_button_for: None,
+    #[test]
+    fn machine_call_fails_in_strict_mode() {
+        let mut cpu = cpu_with(&[0x01, 0x23]);
+        cpu.strict = true;
+
+        assert!(matches!(cpu.step(), Err(ExecError::UnsupportedMachineCall(0x123))));
+        assert!(cpu.is_halted());
+    }
+    #[test]
+    fn machine_call_is_ignored() {
+        let mut cpu = cpu_with(&[0x01, 0x23]);
+        let v = cpu.v;
+
+        assert!(cpu.step().is_ok());
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.v, v);
+        assert_eq!(cpu.ignored.get(&0x0123), Some(&1));
+    }
+
+    #[test]
+    fn draw_wraps_past_memory_end() {
+        // D01F
+        let mut cpu = cpu_with(&[0xD0, 0x1F]);
+        cpu.i = 0xFFA;
+        cpu.memory[0xFFA] = 0xFF;
+        cpu.memory[0] = 0x80;
+
+        cpu.step().unwrap();
+        assert!(cpu.display[0]);
+        // 7th row is the first byte of the memory
+        assert!(cpu.display[6 * cpu.width() as usize]);
+    }
+    #[test]
+    fn store_and_read_wrap_past_memory_end() {
+        // F233, F255, F265
+        let mut cpu = cpu_with(&[0xF2, 0x33, 0xF2, 0x55, 0xF2, 0x65]);
+        cpu.quirks.load_store_increments_i = false;
+        cpu.i = 0xFFF;
+        cpu.set(0, 1);
+        cpu.set(1, 2);
+        cpu.set(2, 234);
+
+        cpu.step().unwrap();
+        assert_eq!([cpu.memory[0xFFF], cpu.memory[0], cpu.memory[1]], [2, 3, 4]);
+
+        cpu.step().unwrap();
+        assert_eq!([cpu.memory[0xFFF], cpu.memory[0], cpu.memory[1]], [1, 2, 234]);
+
+        cpu.v = [0; 16];
+        cpu.step().unwrap();
+        assert_eq!(&cpu.v[..3], &[1, 2, 234]);
+    }
+    #[test]
+    fn add_i_vx_keeps_16_bits() {
+        // F01E, F255
+        let mut cpu = cpu_with(&[0xF0, 0x1E, 0xF2, 0x55]);
+        cpu.quirks.index_overflow_sets_vf = false;
+        cpu.i = 0xFF0;
+        cpu.set(0, 0x20);
+        cpu.set(0xF, 7);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.i(), 0x1010);
+        assert_eq!(cpu.get(0xF), 7);
+
+        // Stored at 0x010
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory[0x010], 0x20);
+    }
+    #[test]
+    fn add_i_vx_sets_vf_on_overflow() {
+        // F01E
+        let mut cpu = cpu_with(&[0xF0, 0x1E, 0xF0, 0x1E]);
+        cpu.quirks.index_overflow_sets_vf = true;
+        cpu.i = 0xFF0;
+        cpu.set(0, 0x20);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.i(), 0x010);
+        assert_eq!(cpu.get(0xF), 1);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.i(), 0x030);
+        assert_eq!(cpu.get(0xF), 0);
+    }
+    #[test]
+    fn index_past_memory_fails_in_strict_mode() {
+        // F01E, F255
+        let mut cpu = cpu_with(&[0xF0, 0x1E, 0xF2, 0x55]);
+        cpu.quirks.index_overflow_sets_vf = false;
+        cpu.strict = true;
+        cpu.i = 0xFF0;
+        cpu.set(0, 0x20);
+
+        cpu.step().unwrap();
+        assert!(matches!(cpu.step(), Err(ExecError::IndexOutOfMemory(0x1010))));
+    }
+    #[test]
+    fn store_range_ascending() {
+        // 5132
+        let mut cpu = cpu_with(&[0x51, 0x32]);
+        cpu.i = 0x300;
+        cpu.v[1..=3].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        cpu.step().unwrap();
+        assert_eq!(&cpu.memory[0x300..0x303], &[0x11, 0x22, 0x33]);
+        assert_eq!(cpu.i(), 0x300);
+    }
+    #[test]
+    fn store_range_descending() {
+        // 5312
+        let mut cpu = cpu_with(&[0x53, 0x12]);
+        cpu.i = 0x300;
+        cpu.v[1..=3].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        cpu.step().unwrap();
+        assert_eq!(&cpu.memory[0x300..0x303], &[0x33, 0x22, 0x11]);
+        assert_eq!(cpu.i(), 0x300);
+    }
+    #[test]
+    fn read_range_ascending() {
+        // 5133
+        let mut cpu = cpu_with(&[0x51, 0x33]);
+        cpu.i = 0x300;
+        cpu.memory[0x300..0x303].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        cpu.step().unwrap();
+        assert_eq!(&cpu.v[..5], &[0, 0x11, 0x22, 0x33, 0]);
+        assert_eq!(cpu.i(), 0x300);
+    }
+    #[test]
+    fn read_range_descending() {
+        // 5313
+        let mut cpu = cpu_with(&[0x53, 0x13]);
+        cpu.i = 0x300;
+        cpu.memory[0x300..0x303].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        cpu.step().unwrap();
+        assert_eq!(&cpu.v[..5], &[0, 0x33, 0x22, 0x11, 0]);
+        assert_eq!(cpu.i(), 0x300);
+    }
+    #[test]
+    fn release_all_buttons_clears_buttons() {
+        let mut cpu = cpu_with(&[0x12, 0x00]);
+        cpu.button_pressed(0x1);
+        cpu.button_pressed(0xF);
+
+        cpu.release_all_buttons();
+        assert!((0..16).all(|btn| !cpu.is_btn_pressed(btn)));
+    }
+    #[test]
+    fn release_all_buttons_keeps_waiting() {
+        // F30A, 6001
+        let mut cpu = cpu_with(&[0xF3, 0x0A, 0x60, 0x01]);
+        assert!(cpu.step().unwrap().waiting);
+
+        cpu.release_all_buttons();
+        assert_eq!(cpu.pc(), 0x200);
+
+        // Fx0A is executed again instead of being skipped
+        assert!(cpu.step().unwrap().waiting);
+        assert!(cpu.step().unwrap().waiting);
+        assert_eq!(cpu.get(0), 0);
+
+        cpu.button_pressed(0xA);
+        assert_eq!(cpu.get(3), 0xA);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get(0), 1);
+    }
+    #[test]
+    fn index_out_of_memory_fails_in_strict_mode() {
+        // F255
+        let mut cpu = cpu_with(&[0xF2, 0x55]);
+        cpu.strict = true;
+        cpu.i = 0xFFE;
+
+        assert!(matches!(cpu.step(), Err(ExecError::IndexOutOfMemory(0xFFE))));
+        assert!(cpu.is_halted());
+    }
+    #[test]
+    fn pc_out_of_memory_fails_in_strict_mode() {
+        // 1FFF
+        let mut cpu = cpu_with(&[0x1F, 0xFF]);
+        cpu.strict = true;
+
+        cpu.step().unwrap();
+        assert!(matches!(cpu.step(), Err(ExecError::PcOutOfMemory(0xFFF))));
+    }
+    #[test]
+    fn pc_wraps_past_memory_end() {
+        // 1FFF
+        let mut cpu = cpu_with(&[0x1F, 0xFF]);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.pc() < 0x1000);
+    }
+
+    /// Display after running the program that draws the digit 5 at random positions
+    fn random_draws(seed: u32) -> Vec<bool> {
+        // 6005 F029, then loop: C13F C21F D125 1204
+        let mut cpu = cpu_with(&[
+            0x60, 0x05, 0xF0, 0x29,
+            0xC1, 0x3F, 0xC2, 0x1F, 0xD1, 0x25, 0x12, 0x04,
+        ]);
+        cpu.set_seed(seed);
+
+        for _ in 0..400 {
+            cpu.step().unwrap();
         }
+        cpu.display
+    }
+    #[test]
+    fn same_seed_draws_same_display() {
+        let display = random_draws(42);
+
+        assert!(display.contains(&true));
+        assert_eq!(display, random_draws(42));
+    }
+    #[test]
+    fn different_seed_draws_different_display() {
+        assert_ne!(random_draws(42), random_draws(43));
     }
 }
@@ -0,0 +1,69 @@
+//! Errors that stop the app, they are printed by `main` right before the exit
+
+use std::{fmt::{Debug, Display}, io, path::PathBuf};
+
+use winit::error::EventLoopError;
+
+use crate::{
+    app::ContextError,
+    cli::CliError,
+    config::ConfigError,
+    cpu::CpuError,
+    replay::ReplayError,
+    rom::RomError,
+};
+
+// Errors
+/// Game that couldn't be loaded
+#[derive(Debug)]
+pub struct LoadError {
+    pub path: PathBuf,
+    pub err: RomError,
+}
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not read game '{}': {}", self.path.display(), self.err)
+    }
+}
+
+pub enum AppError {
+    Cli(CliError),
+    Config(ConfigError),
+    Load(LoadError),
+    Replay(ReplayError),
+    Poke(CpuError),
+    Entry(CpuError),
+    SmokeTest(PathBuf, io::Error),
+    RawOutput(PathBuf, io::Error),
+    Snapshot(PathBuf, io::Error),
+    Context(ContextError),
+    EventLoop(EventLoopError),
+}
+impl Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cli(e) => write!(f, "{e}"),
+            Self::Config(e) => write!(f, "{e}"),
+            Self::Load(e) => write!(f, "{e}"),
+            Self::Replay(e) => write!(f, "{e}"),
+            Self::Poke(e) => write!(f, "Unable to poke: {e}"),
+            Self::Entry(e) => write!(f, "Unable to set the entry point: {e}"),
+            Self::SmokeTest(p, e) => write!(f, "Could not read directory '{}': {e}", p.display()),
+            Self::RawOutput(p, e) => write!(f, "Unable to open the raw output '{}': {e}", p.display()),
+            Self::Snapshot(p, e) => write!(f, "Unable to write the snapshot '{}': {e}", p.display()),
+            Self::Context(e) => write!(
+                f,
+                "Rendering error: {e}\n\
+                Try updating the graphics drivers, rendering on the CPU with --software\n\
+                or running the game without a window with --bench"
+            ),
+            Self::EventLoop(e) => write!(f, "Window error: {e}"),
+        }
+    }
+}
+/// `main` prints the returned error with [Debug], so it is the same as [Display]
+impl Debug for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
@@ -1,7 +1,7 @@
 /// Number of CHIP-8 font sprite rows
 pub const CHIP_FONT_LEN: usize = 80;
 /// Number of UI font sprite rows
-pub const UI_FONT_LEN: usize = 230;
+pub const UI_FONT_LEN: usize = 235;
 
 /// Contains all the font sprites for CHIP-8 (each char size is 4x5 "pixels")
 /// Yoinked from http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.4
@@ -302,4 +302,10 @@ pub const UI_FONT: [u8; UI_FONT_LEN] = [
     0b11110000,
     0b00000000,
     0b00000000,
+    // :
+    0b00000000,
+    0b01000000,
+    0b00000000,
+    0b01000000,
+    0b00000000,
 ];
@@ -0,0 +1,200 @@
+//! Running the CPU without a window and without audio
+
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io,
+    path::Path,
+    process::Command,
+    time::Instant,
+};
+
+use crate::{
+    config::{Config, RomConfig, DEFAULT_SPEED},
+    cpu::{Cpu, ExecError},
+    png,
+    quirks::{self, Quirks},
+    rom,
+    symbols::Symbols,
+};
+
+/// How many cycles each game is run in [smoke_test]
+const SMOKE_TEST_CYCLES: u64 = 5000;
+/// Game that the child process of [smoke_test] runs
+const SMOKE_TEST_GAME_VAR: &str = "PITCH1002_SMOKE_TEST_GAME";
+
+/// Step the CPU `cycles` times and print how long did it take
+/// Random numbers depend only on the seed, so running the same game with the same seed
+/// always gives the same results
+pub fn bench(cpu: &mut Cpu, cycles: u64, speed: u32, symbols: &Symbols) {
+    let start = Instant::now();
+    let cycles = run(cpu, cycles, speed);
+
+    let secs = start.elapsed().as_secs_f64();
+    let mips = cycles as f64 / secs / 1_000_000.0;
+
+    println!("{} cycles in {:.2} ms ({:.2} MIPS)", cycles, secs * 1000.0, mips);
+    print!("{}", display_text(cpu, symbols));
+}
+
+/// Step the CPU `cycles` times and save the display into a PNG image at `path`,
+/// one image pixel per CHIP-8 pixel in the colors of the current palette
+/// Just like [bench], the image is always the same for the same game, seed and quirks
+pub fn snapshot(cpu: &mut Cpu, cycles: u64, config: &Config, path: &Path) -> io::Result<()> {
+    run(cpu, cycles, config.speed);
+
+    let (w, h) = (cpu.width(), cpu.height());
+    let mut pixels = Vec::with_capacity((w * h * 4) as usize);
+    for i in 0..(w * h) as usize {
+        let color = config.plane_color(cpu.pixel_planes(i));
+        pixels.extend_from_slice(&[color.0, color.1, color.2, 255]);
+    }
+
+    fs::write(path, png::encode(w, h, &pixels))
+}
+
+/// Step the CPU `cycles` times, as fast as possible
+/// Timers are updated every `speed` cycles, just like in a real frame
+/// Returns how many cycles were executed, it is less than `cycles` if the instruction has failed
+pub fn run(cpu: &mut Cpu, cycles: u64, speed: u32) -> u64 {
+    let (executed, err) = run_until_error(cpu, cycles, speed);
+    if let Some(e) = err {
+        eprintln!("Execution error: {}", e);
+    }
+    executed
+}
+/// Same as [run], but returns the error of the failed instruction instead of printing it
+fn run_until_error(cpu: &mut Cpu, cycles: u64, speed: u32) -> (u64, Option<ExecError>) {
+    let mut executed = 0;
+
+    while executed < cycles {
+        executed += 1;
+
+        if let Err(e) = cpu.step() {
+            return (executed, Some(e));
+        }
+
+        if executed % speed as u64 == 0 {
+            cpu.step_timers();
+        }
+    }
+
+    (executed, None)
+}
+
+/// Run every game in the `dir` for a bit in strict mode and print which of them have failed
+/// Quirks are taken from `quirks`, the game settings or guessed with `auto_quirks`
+/// Returns whether all the games have passed
+/// Every game is run in a child process of the same binary (see [smoke_test_game]),
+/// so a game that crashes the emulator fails without ending the whole test
+pub fn smoke_test(
+    dir: &Path,
+    quirks: Option<Quirks>,
+    auto_quirks: bool,
+    display_size: (u32, u32),
+) -> io::Result<bool> {
+    if let Some(path) = env::var_os(SMOKE_TEST_GAME_VAR) {
+        return Ok(smoke_test_game(Path::new(&path), quirks, auto_quirks, display_size));
+    }
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file() && p.extension().is_some_and(|e| e == "ch8" || e == "zip"));
+    paths.sort();
+
+    // Child gets the same arguments, so it runs the game with the same settings
+    let exe = env::current_exe()?;
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+
+    let mut failed = 0;
+    for path in &paths {
+        let status = Command::new(&exe)
+            .args(&args)
+            .env(SMOKE_TEST_GAME_VAR, path)
+            .status()?;
+
+        match status.code() {
+            Some(0) => (),
+            // Child has already printed why
+            Some(1) => failed += 1,
+            // Panic message is printed by the child into stderr
+            _ => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                println!("FAIL {}: crashed ({})", name, status);
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {} failed", paths.len() - failed, failed);
+    Ok(failed == 0)
+}
+/// Run a single game of [smoke_test] and print whether it has passed
+/// Panics end the process, [smoke_test] reports them from the outside
+fn smoke_test_game(path: &Path, quirks: Option<Quirks>, auto_quirks: bool, display_size: (u32, u32)) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    let game = match rom::read(path) {
+        Ok(game) => game,
+        Err(e) => {
+            println!("FAIL {}: {}", name, e);
+            return false;
+        }
+    };
+
+    let mut cpu = Cpu::with_display(display_size.0, display_size.1);
+    cpu.quirks = quirks
+        .or_else(|| RomConfig::load(path).ok().and_then(|c| c.quirks))
+        .or_else(|| auto_quirks.then(|| quirks::detect(&game).1))
+        .unwrap_or_default();
+    cpu.strict = true;
+    cpu.load(&game);
+
+    match run_until_error(&mut cpu, SMOKE_TEST_CYCLES, DEFAULT_SPEED) {
+        (_, None) => {
+            println!("PASS {}", name);
+            true
+        }
+        (executed, Some(e)) => {
+            println!("FAIL {}: {} (cycle {}, PC {:#05X})", name, e, executed, cpu.pc());
+            false
+        }
+    }
+}
+
+/// Format all the registers in a single line, for example:
+/// "V0=00 V1=1F ... VF=01 I=0x2A0 PC=0x21C <main_loop> SP=1 DT=0 ST=0"
+/// Addresses are labeled with the `symbols`
+pub fn registers_text(cpu: &Cpu, symbols: &Symbols) -> String {
+    let mut text = String::new();
+    for x in 0..16 {
+        text.push_str(&format!("V{:X}={:02X} ", x, cpu.get(x)));
+    }
+
+    text + &format!(
+        "I={} PC={} SP={} DT={} ST={}",
+        symbols.annotate(cpu.i()),
+        symbols.annotate(cpu.pc()),
+        cpu.sp(),
+        cpu.delay_timer(),
+        cpu.sound_timer(),
+    )
+}
+
+/// Format the display as a grid of `#` (on) and `.` (off) pixels,
+/// with a header line showing PC and I (labeled with the `symbols`)
+pub fn display_text(cpu: &Cpu, symbols: &Symbols) -> String {
+    let w = cpu.width() as usize;
+    let h = cpu.height() as usize;
+    let mut text = format!("PC={} I={}\n", symbols.annotate(cpu.pc()), symbols.annotate(cpu.i()));
+
+    for row in cpu.display.chunks_exact(w).take(h) {
+        text.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+        text.push('\n');
+    }
+
+    text
+}
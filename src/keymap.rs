@@ -0,0 +1,155 @@
+//! Names of the keyboard keys, so they can be specified in the command line,
+//! and the keys bound to the app actions
+
+use winit::keyboard::KeyCode;
+
+/// Every key that can be specified by name
+/// Names are the characters printed on the keys of the QWERTY keyboard, where possible
+const KEY_NAMES: [(&str, KeyCode); 63] = [
+    ("a", KeyCode::KeyA), ("b", KeyCode::KeyB), ("c", KeyCode::KeyC), ("d", KeyCode::KeyD),
+    ("e", KeyCode::KeyE), ("f", KeyCode::KeyF), ("g", KeyCode::KeyG), ("h", KeyCode::KeyH),
+    ("i", KeyCode::KeyI), ("j", KeyCode::KeyJ), ("k", KeyCode::KeyK), ("l", KeyCode::KeyL),
+    ("m", KeyCode::KeyM), ("n", KeyCode::KeyN), ("o", KeyCode::KeyO), ("p", KeyCode::KeyP),
+    ("q", KeyCode::KeyQ), ("r", KeyCode::KeyR), ("s", KeyCode::KeyS), ("t", KeyCode::KeyT),
+    ("u", KeyCode::KeyU), ("v", KeyCode::KeyV), ("w", KeyCode::KeyW), ("x", KeyCode::KeyX),
+    ("y", KeyCode::KeyY), ("z", KeyCode::KeyZ),
+
+    ("0", KeyCode::Digit0), ("1", KeyCode::Digit1), ("2", KeyCode::Digit2), ("3", KeyCode::Digit3),
+    ("4", KeyCode::Digit4), ("5", KeyCode::Digit5), ("6", KeyCode::Digit6), ("7", KeyCode::Digit7),
+    ("8", KeyCode::Digit8), ("9", KeyCode::Digit9),
+
+    ("-", KeyCode::Minus), ("=", KeyCode::Equal),
+    ("[", KeyCode::BracketLeft), ("]", KeyCode::BracketRight), ("\\", KeyCode::Backslash),
+
+    ("f1", KeyCode::F1), ("f2", KeyCode::F2), ("f3", KeyCode::F3), ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5), ("f6", KeyCode::F6), ("f7", KeyCode::F7), ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9), ("f10", KeyCode::F10), ("f11", KeyCode::F11), ("f12", KeyCode::F12),
+
+    ("escape", KeyCode::Escape), ("space", KeyCode::Space), ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab), ("backspace", KeyCode::Backspace), ("pause", KeyCode::Pause),
+    ("up", KeyCode::ArrowUp), ("down", KeyCode::ArrowDown),
+    ("left", KeyCode::ArrowLeft), ("right", KeyCode::ArrowRight),
+];
+
+/// Parse a key name like "p", "f5" or "space" (case insensitive)
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+
+    KEY_NAMES.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, key)| *key)
+}
+
+/// Name of the key, if it can be specified by name
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEY_NAMES.iter()
+        .find(|(_, k)| *k == key)
+        .map(|(n, _)| *n)
+}
+
+/// App actions that can be bound to any key
+/// Esc always leaves the fullscreen or toggles pause, the rest of the F keys are not remappable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pause,
+    FastForward,
+    Mute,
+    NextPalette,
+    PrevPalette,
+    RandomPalette,
+    SpeedUp,
+    SpeedDown,
+    ResetSpeed,
+    Restart,
+    HighlightDraws,
+    Timer,
+    CopyScreen,
+}
+impl Action {
+    pub const ALL: [Self; 13] = [
+        Self::Pause,
+        Self::FastForward,
+        Self::Mute,
+        Self::NextPalette,
+        Self::PrevPalette,
+        Self::RandomPalette,
+        Self::SpeedUp,
+        Self::SpeedDown,
+        Self::ResetSpeed,
+        Self::Restart,
+        Self::HighlightDraws,
+        Self::Timer,
+        Self::CopyScreen,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == s)
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::FastForward => "fast-forward",
+            Self::Mute => "mute",
+            Self::NextPalette => "next-palette",
+            Self::PrevPalette => "prev-palette",
+            Self::RandomPalette => "random-palette",
+            Self::SpeedUp => "speed-up",
+            Self::SpeedDown => "speed-down",
+            Self::ResetSpeed => "reset-speed",
+            Self::Restart => "restart",
+            Self::HighlightDraws => "highlight-draws",
+            Self::Timer => "timer",
+            Self::CopyScreen => "copy-screen",
+        }
+    }
+}
+
+/// Key of every [Action]
+/// Bound keys can't press the CHIP-8 buttons
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: [KeyCode; Action::ALL.len()],
+}
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: [
+                KeyCode::KeyP,
+                KeyCode::Space,
+                KeyCode::KeyM,
+                KeyCode::BracketRight,
+                KeyCode::BracketLeft,
+                KeyCode::Backslash,
+                KeyCode::Equal,
+                KeyCode::Minus,
+                KeyCode::Digit0,
+                KeyCode::Enter,
+                KeyCode::KeyH,
+                KeyCode::KeyT,
+                KeyCode::F1,
+            ],
+        }
+    }
+}
+impl Bindings {
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        self.keys[action as usize] = key;
+    }
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys[action as usize]
+    }
+
+    /// Action bound to the `key`
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL.into_iter().find(|&a| self.key(a) == key)
+    }
+
+    /// First two actions bound to the same key, if any
+    pub fn conflict(&self) -> Option<(Action, Action)> {
+        Action::ALL.into_iter().enumerate().find_map(|(i, a)| {
+            Action::ALL[i + 1..].iter()
+                .find(|&&b| self.key(a) == self.key(b))
+                .map(|&b| (a, b))
+        })
+    }
+}
@@ -0,0 +1,77 @@
+//! Debug logs printed to the terminal with `--verbose`, they are off by default
+//! Logging can be removed from the build by disabling the `log` feature
+//! Messages for the user are printed with [message!], they are always on
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the messages are printed into stderr (see [set_messages_to_stderr])
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Print a message for the user, same as `println!`, but it goes into stderr
+/// when stdout is taken (see [set_messages_to_stderr])
+#[macro_export]
+macro_rules! message {
+    ($($arg:tt)*) => {
+        if $crate::logger::messages_to_stderr() { eprintln!($($arg)*) }
+        else { println!($($arg)*) }
+    };
+}
+
+/// Print the messages into stderr from now on, because stdout is taken
+/// by the raw frames (see `--raw-output -`)
+pub fn set_messages_to_stderr() {
+    MESSAGES_TO_STDERR.store(true, Ordering::Relaxed);
+}
+pub fn messages_to_stderr() -> bool {
+    MESSAGES_TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// Log a debug message, does nothing if the `log` feature is disabled
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        {
+            #[cfg(feature = "log")]
+            log::debug!($($arg)*);
+            #[cfg(not(feature = "log"))]
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "log")]
+struct Logger {
+    /// Print the logs of the other crates too (wgpu, winit, etc.)
+    everything: bool,
+}
+#[cfg(feature = "log")]
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.everything || metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+    fn flush(&self) {}
+}
+
+/// Start printing the logs
+/// `verbosity` 0 - nothing, 1 - PITCH1002 debug logs, 2 - all the logs of all the crates
+pub fn init(verbosity: u8) {
+    if verbosity == 0 { return }
+
+    #[cfg(feature = "log")]
+    {
+        let logger = Box::new(Logger { everything: verbosity > 1 });
+        if log::set_boxed_logger(logger).is_ok() {
+            log::set_max_level(
+                if verbosity > 1 { log::LevelFilter::Trace }
+                else { log::LevelFilter::Debug }
+            );
+        }
+    }
+    #[cfg(not(feature = "log"))]
+    eprintln!("PITCH1002 was built without the \"log\" feature, logs are unavailable");
+}
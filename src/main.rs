@@ -4,39 +4,203 @@ mod font;
 mod config;
 mod cli;
 mod buzzer;
+mod quirks;
+mod headless;
+mod replay;
+mod rng;
+mod timing;
+mod logger;
+mod rom;
+mod keymap;
+mod png;
+mod error;
+mod symbols;
 
-use std::io::Read;
+use std::{path::{Path, PathBuf}, time::Duration};
 
-use app::App;
+use app::{App, Compare, RawOutput};
 use buzzer::Buzzer;
-use cli::Cli;
-use config::Config;
+use cli::{Cli, Poke};
+use config::{Config, EnvConfig, RomConfig, SavedState};
 use cpu::Cpu;
+use error::{AppError, LoadError};
+use quirks::Quirks;
+use replay::{InputLog, Recorder, Replay};
 use winit::{
-    error::EventLoopError,
     event_loop::{ControlFlow, EventLoop},
     platform::run_on_demand::EventLoopExtRunOnDemand,
 };
 
-fn main() -> Result<(), EventLoopError> {
-    let cli = match Cli::new() {
-        Ok(cli) => cli,
-        Err(e) => {
-            eprintln!("{}", e);
-            std::process::exit(1);
+/// Built-in game, played when no game is specified
+const DEMO_GAME: &[u8] = include_bytes!("../roms/ferris.ch8");
+const DEMO_NAME: &str = "ferris.ch8";
+
+/// Read the game at `path`
+fn read_game(path: &Path) -> Result<Vec<u8>, AppError> {
+    rom::read(path).map_err(|err| AppError::Load(LoadError { path: path.to_path_buf(), err }))
+}
+
+/// Create a CPU, load the game into it and apply the pokes
+fn init_cpu(game: &[u8], quirks: Quirks, config: &Config, pokes: &[Poke]) -> Result<Cpu, AppError> {
+    let (width, height) = config.display_size;
+    let mut cpu = Cpu::with_display(width, height);
+    cpu.quirks = quirks;
+    cpu.strict = config.strict;
+    cpu.set_seed(config.seed);
+    cpu.load(game);
+    if let Some(entry) = config.entry {
+        cpu.set_entry(entry).map_err(AppError::Entry)?;
+    }
+
+    for &poke in pokes {
+        let res = match poke {
+            Poke::Memory(addr, value) => cpu.poke(addr, value),
+            Poke::Register(x, value) => cpu.set_register(x, value),
+            Poke::DelayTimer(value) => {
+                cpu.set_delay_timer(value);
+                Ok(())
+            }
+            Poke::SoundTimer(value) => {
+                cpu.set_sound_timer(value);
+                Ok(())
+            }
+        };
+
+        res.map_err(AppError::Poke)?;
+    }
+
+    Ok(cpu)
+}
+
+fn main() -> Result<(), AppError> {
+    let mut cli = Cli::new().map_err(AppError::Cli)?;
+
+    logger::init(cli.verbose);
+    // Messages would break the frames streamed into stdout
+    if cli.raw_output.as_deref() == Some(Path::new("-")) {
+        logger::set_messages_to_stderr();
+    }
+
+    if cli.list_audio_devices {
+        for name in buzzer::output_device_names() {
+            println!("{}", name);
         }
-    };
+        return Ok(());
+    }
+
+    if let Some(dir) = &cli.smoke_test {
+        let display_size = cli.display_size.unwrap_or((cpu::DISPLAY_WIDTH, cpu::DISPLAY_HEIGHT));
+        match headless::smoke_test(dir, cli.quirks, cli.auto_quirks, display_size) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => return Err(AppError::SmokeTest(dir.clone(), e)),
+        }
+    }
 
     // Read game binary
-    // TEMP: For now you should guarantee that you will specify at least one game!
-    let mut file = std::fs::File::open(&cli.game_paths.as_ref().expect("Specify the path to the game")[0]).unwrap();
-    let mut game = vec![];
-    file.read_to_end(&mut game).unwrap();
+    // Play the built-in game if no game was specified
+    let game_path = cli.game_paths.as_ref()
+        .and_then(|paths| paths.first().cloned())
+        .filter(|_| !cli.demo);
+    let (game_path, game) = match game_path {
+        Some(game_path) => {
+            let game = read_game(&game_path)?;
+            (game_path, Some(game))
+        }
+        None => (PathBuf::from(DEMO_NAME), None),
+    };
+
+    // Init config
+    // The built-in game has no settings file
+    let rom_config = match &game {
+        Some(_) => RomConfig::load(&game_path),
+        None => Ok(RomConfig::default()),
+    };
+    let mut rom_config = rom_config.map_err(AppError::Config)?;
+    let game = game.unwrap_or_else(|| DEMO_GAME.to_vec());
+
+    // Guess the quirks only if they weren't specified explicitly
+    if cli.auto_quirks && cli.quirks.is_none() && rom_config.quirks.is_none() {
+        let (profile, quirks) = quirks::detect(&game);
+        message!("Auto quirks: using \"{}\" profile", profile);
+        rom_config.quirks = Some(quirks);
+    }
+
+    let env_config = EnvConfig::load().map_err(AppError::Config)?;
+
+    // Restore the preferences only if asked
+    let state = if cli.remember {
+        SavedState::load().unwrap_or_else(|e| {
+            eprintln!("Unable to restore the preferences: {}", e);
+            SavedState::default()
+        })
+    } else {
+        SavedState::default()
+    };
+
+    // Replay takes the control from the user, so there's nothing to record
+    let input_log = if let Some(path) = &cli.replay {
+        Some(Replay::load(path).map(InputLog::Replay))
+    } else {
+        cli.record.as_ref().map(|path| Recorder::create(path).map(InputLog::Record))
+    };
+    let input_log = input_log.transpose().map_err(AppError::Replay)?;
+
+    // Compared game is the same game by default, just with other quirks
+    let compare_game = cli.compare_game.as_deref().map(read_game).transpose()?;
+    let compare = cli.compare_game.is_some() || cli.compare_quirks.is_some();
+    let compare_quirks = cli.compare_quirks;
+
+    let mute = cli.mute || env_config.mute.or(state.muted).unwrap_or(false);
+    let bench = cli.bench;
+    let snapshot = cli.snapshot.take();
+    let warmup = cli.warmup;
+    let audio_device = cli.audio_device.take();
+    let pokes = std::mem::take(&mut cli.pokes);
+    let raw_output = cli.raw_output.take();
+    let raw_scale = cli.raw_scale.unwrap_or(1);
+    let config = Config::new(cli, env_config, rom_config, state).map_err(AppError::Config)?;
+
+    if config.unsafe_speed {
+        eprintln!("WARNING: speed is not limited with --unsafe-speed!");
+        eprintln!("WARNING: the window may freeze at very high speeds, use it only for benchmarking");
+    }
+
+    // Init cpu
+    debug!("Quirks: {:?}", config.quirks);
+    let mut cpu = init_cpu(&game, config.quirks, &config, &pokes)?;
+
+    // Both games start in the same state
+    let mut compare = compare.then(|| {
+        let quirks = compare_quirks.unwrap_or(config.quirks);
+        debug!("Compared quirks: {:?}", quirks);
+
+        init_cpu(compare_game.as_deref().unwrap_or(&game), quirks, &config, &pokes)
+            .map(|cpu| Compare { cpu, same_game: compare_game.is_none() })
+    }).transpose()?;
+
+    // Skip the boring init of the game
+    if let Some(cycles) = warmup {
+        headless::run(&mut cpu, cycles, config.speed);
+        if let Some(compare) = &mut compare {
+            headless::run(&mut compare.cpu, cycles, config.speed);
+        }
+    }
+
+    if let Some(cycles) = bench {
+        headless::bench(&mut cpu, cycles, config.speed, &config.symbols);
+        return Ok(());
+    }
+    if let Some((cycles, path)) = snapshot {
+        return headless::snapshot(&mut cpu, cycles, &config, &path)
+            .map_err(|e| AppError::Snapshot(path, e));
+    }
 
     // Init buzzer
-    let buzzer = match Buzzer::new() {
+    let buzzer = match Buzzer::new(audio_device.as_deref()) {
         Ok(mut buzzer) => {
-            buzzer.set_muted(cli.mute);
+            buzzer.set_muted(mute);
+            buzzer.set_min_duration(Duration::from_millis(config.min_beep as u64 * app::TARGET_DELAY));
             Some(buzzer)
         }
         Err(e) => {
@@ -46,19 +210,35 @@ fn main() -> Result<(), EventLoopError> {
         }
     };
 
-    // Init config
-    let config = Config::new(cli);
-
-    // Init cpu
-    let mut cpu = Cpu::default();
-    cpu.load(&game);
-
-    let mut event_loop = EventLoop::new()?;
+    let mut event_loop = EventLoop::new().map_err(AppError::EventLoop)?;
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut win = App::new(config, cpu, buzzer);
+    // Frames are as big as the window texture
+    let raw_output = match &raw_output {
+        Some(path) => match RawOutput::open(path, raw_scale) {
+            Ok(raw) => {
+                let displays = if compare.is_some() { 2 } else { 1 };
+                let (w, h) = raw.frame_size(cpu.width() * displays, cpu.height());
+                eprintln!("Raw output: {}x{} RGBA frames, 60 per second", w, h);
+                Some(raw)
+            }
+            Err(e) => return Err(AppError::RawOutput(path.clone(), e)),
+        },
+        None => None,
+    };
+
+    let mut win = App::new(config, cpu, compare, buzzer, game_path, input_log, raw_output);
     
     // If i use `run_app`, a segmentation fault occurs after closing the app
     // but with `run_app_on_demand` it works well (please just tell me, that i am stupid)
-    event_loop.run_app_on_demand(&mut win)
+    event_loop.run_app_on_demand(&mut win).map_err(AppError::EventLoop)?;
+
+    if let Some(e) = win.take_context_error() {
+        return Err(AppError::Context(e));
+    }
+
+    if let Some(code) = win.exit_code() {
+        std::process::exit(code);
+    }
+    Ok(())
 }
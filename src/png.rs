@@ -0,0 +1,74 @@
+//! Just enough of the PNG format to save the screen
+//! Pixels are stored without compression, the screenshots are small anyway
+//!
+//! More info:
+//! - https://www.w3.org/TR/png/
+//! - https://www.rfc-editor.org/rfc/rfc1950 (zlib)
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// Max length of the stored deflate block
+const MAX_BLOCK: usize = 0xFFFF;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encode `width` x `height` RGBA `pixels` (row by row) as a PNG image
+pub fn encode(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut out = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header);
+
+    // Every row starts with the filter type, 0 is no filter
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks_exact(width as usize * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // Zlib stream of the stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let blocks = raw.chunks(MAX_BLOCK).count();
+    for (i, block) in raw.chunks(MAX_BLOCK).enumerate() {
+        let last = i + 1 == blocks;
+        let len = block.len() as u16;
+        data.push(u8::from(last));
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&raw).to_be_bytes());
+    write_chunk(&mut out, b"IDAT", &data);
+
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
@@ -0,0 +1,162 @@
+//! CHIP-8 interpreters disagree on how some instructions behave, and games usually rely on the
+//! behaviour of the interpreter they were written for
+//!
+//! More info:
+//! - https://chip8.gulrak.net/#quirk-definitions
+
+/// Behaviours that differ between the CHIP-8 interpreters
+/// Default is the classic PITCH1002 behaviour (all quirks disabled)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` store shifted Vy in Vx, instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// `Fx55`/`Fx65` leave I pointing right after the last stored/read register
+    pub load_store_increments_i: bool,
+    /// `8xy1`/`8xy2`/`8xy3` reset VF to 0
+    pub vf_reset: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around
+    pub clip_sprites: bool,
+    /// `Bxnn` jumps to `xnn + Vx`, instead of `Bnnn` jumping to `nnn + V0`
+    pub jump_offset_uses_vx: bool,
+    /// `Fx1E` sets VF to 1 when I goes past 0xFFF and to 0 otherwise, like the Amiga interpreter
+    pub index_overflow_sets_vf: bool,
+}
+impl Quirks {
+    /// Original COSMAC VIP CHIP-8 behaviour
+    pub const CHIP8: Self = Self {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        vf_reset: true,
+        clip_sprites: true,
+        jump_offset_uses_vx: false,
+        index_overflow_sets_vf: false,
+    };
+    /// SUPER-CHIP behaviour
+    pub const SCHIP: Self = Self {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        vf_reset: false,
+        clip_sprites: true,
+        jump_offset_uses_vx: true,
+        index_overflow_sets_vf: false,
+    };
+
+    /// Parse quirks from a comma separated list of profile and quirk names
+    /// For example: "chip8", "schip,vf-reset" or "shift,clip"
+    ///
+    /// Profiles (`chip8`, `schip`, `none`) replace all the quirks specified before them,
+    /// quirk names (see [QUIRK_LIST]) enable a single quirk
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut quirks = Self::default();
+
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "none" => quirks = Self::default(),
+                "chip8" => quirks = Self::CHIP8,
+                "schip" => quirks = Self::SCHIP,
+
+                name => {
+                    let quirk = QUIRK_LIST.iter().find(|q| q.name == name)?;
+                    *(quirk.field)(&mut quirks) = true;
+                }
+            }
+        }
+
+        Some(quirks)
+    }
+}
+
+/// Single quirk that can be enabled with [Quirks::parse]
+pub struct QuirkInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub field: fn(&mut Quirks) -> &mut bool,
+}
+pub const QUIRK_LIST: [QuirkInfo; 6] = [
+    QuirkInfo {
+        name: "shift",
+        description: "8xy6/8xyE store shifted Vy in Vx, instead of shifting Vx in place",
+        field: |q| &mut q.shift_uses_vy,
+    },
+    QuirkInfo {
+        name: "load-store",
+        description: "Fx55/Fx65 leave I pointing right after the last stored/read register",
+        field: |q| &mut q.load_store_increments_i,
+    },
+    QuirkInfo {
+        name: "vf-reset",
+        description: "8xy1/8xy2/8xy3 reset VF to 0",
+        field: |q| &mut q.vf_reset,
+    },
+    QuirkInfo {
+        name: "clip",
+        description: "Sprites are clipped at the edges of the screen instead of wrapping around",
+        field: |q| &mut q.clip_sprites,
+    },
+    QuirkInfo {
+        name: "jump",
+        description: "Bxnn jumps to xnn + Vx, instead of Bnnn jumping to nnn + V0",
+        field: |q| &mut q.jump_offset_uses_vx,
+    },
+    QuirkInfo {
+        name: "index-overflow",
+        description: "Fx1E sets VF to 1 when I goes past 0xFFF and to 0 otherwise",
+        field: |q| &mut q.index_overflow_sets_vf,
+    },
+];
+
+/// Print every quirk, what it does and whether it is enabled in the profiles
+pub fn print_list() {
+    let state = |mut quirks: Quirks, quirk: &QuirkInfo| {
+        if *(quirk.field)(&mut quirks) { "on" } else { "off" }
+    };
+
+    println!("QUIRKS:");
+    for quirk in &QUIRK_LIST {
+        println!("    {:<16}{}", quirk.name, quirk.description);
+        println!(
+            "    {:<16}none: {}, chip8: {}, schip: {}",
+            "",
+            state(Quirks::default(), quirk),
+            state(Quirks::CHIP8, quirk),
+            state(Quirks::SCHIP, quirk),
+        );
+    }
+}
+
+/// Guess which quirks the game expects by looking for SUPER-CHIP only instructions in it
+/// Returns the name of the chosen profile and the quirks
+///
+/// This is only a heuristic! Sprite data may look like an instruction too, so a few SUPER-CHIP
+/// instructions are required to be sure
+pub fn detect(rom: &[u8]) -> (&'static str, Quirks) {
+    /// How many SUPER-CHIP instructions needed to choose the SUPER-CHIP profile
+    const SCHIP_THRESHOLD: usize = 2;
+
+    let schip_count = rom
+        .chunks_exact(2)
+        .map(|ins| ((ins[0] as u16) << 8) | ins[1] as u16)
+        .filter(|&ins| is_schip_instruction(ins))
+        .count();
+
+    if schip_count >= SCHIP_THRESHOLD {
+        ("schip", Quirks::SCHIP)
+    } else {
+        ("chip8", Quirks::CHIP8)
+    }
+}
+
+/// Returns whether the instruction exists only in SUPER-CHIP
+pub fn is_schip_instruction(ins: u16) -> bool {
+    match (ins & 0xF000, ins & 0x00FF, ins & 0x000F) {
+        // Scroll right/left, exit, low/high resolution
+        (0, 0xFB..=0xFF, _) => ins & 0x0F00 == 0,
+        // Scroll down by N
+        (0, 0xC1..=0xCF, _) => ins & 0x0F00 == 0,
+        // Draw a 16x16 sprite
+        (0xD000, _, 0) => true,
+        // Point I to a large font sprite, save/load flags
+        (0xF000, 0x30 | 0x75 | 0x85, _) => true,
+        _ => false
+    }
+}
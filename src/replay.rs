@@ -0,0 +1,170 @@
+//! Recording the input into a file and playing it back
+//!
+//! Every line of the input log is an event happened right before the emulated frame:
+//! "<FRAME> press <BUTTON>", "<FRAME> release <BUTTON>", "<FRAME> speed <SPEED>",
+//! "<FRAME> seed <SEED>" or "<FRAME> restart"
+//!
+//! Random numbers depend only on the seed and timers depend only on the executed cycles,
+//! so replaying the log with the same game and quirks gives exactly the same display
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+// Errors
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, io::Error),
+    InvalidLine(PathBuf, usize),
+}
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(p, e) => write!(f, "Unable to open {}: {e}", p.display()),
+            Self::InvalidLine(p, l) => write!(f, "{}:{l}: Invalid input log event", p.display()),
+        }
+    }
+}
+
+/// Something that affects the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// CHIP-8 button was pressed
+    Press(u8),
+    /// CHIP-8 button was released
+    Release(u8),
+    /// Cycles per frame were changed
+    Speed(u32),
+    /// Random numbers were started over from the seed
+    Seed(u32),
+    /// Game was restarted
+    Restart,
+}
+impl Event {
+    /// Parse an event without the frame number, for example "press A" or "speed 20"
+    fn parse(s: &str) -> Option<Self> {
+        let mut words = s.split_whitespace();
+        let event = match words.next()? {
+            "press" => Self::Press(parse_btn(words.next()?)?),
+            "release" => Self::Release(parse_btn(words.next()?)?),
+            "speed" => Self::Speed(words.next()?.parse().ok().filter(|s| *s > 0)?),
+            "seed" => Self::Seed(words.next()?.parse().ok()?),
+            "restart" => Self::Restart,
+            _ => return None
+        };
+
+        // Nothing else is expected after the event
+        match words.next() {
+            Some(_) => None,
+            None => Some(event)
+        }
+    }
+}
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Press(btn) => write!(f, "press {btn:X}"),
+            Self::Release(btn) => write!(f, "release {btn:X}"),
+            Self::Speed(speed) => write!(f, "speed {speed}"),
+            Self::Seed(seed) => write!(f, "seed {seed}"),
+            Self::Restart => write!(f, "restart"),
+        }
+    }
+}
+
+fn parse_btn(s: &str) -> Option<u8> {
+    u8::from_str_radix(s, 16).ok().filter(|btn| *btn <= 0xF)
+}
+
+/// Writes the events into the input log
+pub struct Recorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, ReplayError> {
+        let file = File::create(path)
+            .map_err(|e| ReplayError::Io(path.to_path_buf(), e))?;
+
+        let mut recorder = Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+        };
+        recorder.write_line(format_args!("# PITCH1002 input log"))?;
+
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, frame: u64, event: Event) -> Result<(), ReplayError> {
+        self.write_line(format_args!("{frame} {event}"))
+    }
+
+    /// Write buffered events into the file
+    pub fn flush(&mut self) -> Result<(), ReplayError> {
+        self.file.flush()
+            .map_err(|e| ReplayError::Io(self.path.clone(), e))
+    }
+
+    fn write_line(&mut self, line: std::fmt::Arguments) -> Result<(), ReplayError> {
+        writeln!(self.file, "{line}")
+            .map_err(|e| ReplayError::Io(self.path.clone(), e))
+    }
+}
+
+/// Events read from the input log, waiting for their frame
+pub struct Replay {
+    events: VecDeque<(u64, Event)>,
+}
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| ReplayError::Io(path.to_path_buf(), e))?;
+
+        let mut events = VecDeque::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            // Skip empty lines and comments
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ReplayError::InvalidLine(path.to_path_buf(), index + 1);
+
+            let (frame, event) = line.split_once(' ').ok_or_else(invalid)?;
+            let frame = frame.parse::<u64>().map_err(|_| invalid())?;
+            let event = Event::parse(event).ok_or_else(invalid)?;
+
+            events.push_back((frame, event));
+        }
+
+        Ok(Self { events })
+    }
+
+    /// Take all the events that should happen before the `frame` is emulated
+    pub fn take_events(&mut self, frame: u64) -> Vec<Event> {
+        let mut events = vec![];
+        while let Some(&(event_frame, event)) = self.events.front() {
+            if event_frame > frame { break }
+
+            self.events.pop_front();
+            events.push(event);
+        }
+        events
+    }
+
+    /// Whether all the events were already taken
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// What to do with the user input
+pub enum InputLog {
+    Record(Recorder),
+    Replay(Replay),
+}
@@ -0,0 +1,43 @@
+//! Tiny pseudo random number generator, good enough for the emulator and nothing else
+
+/// Seed used when it wasn't specified
+pub const DEFAULT_SEED: u32 = 1002;
+
+/// Xorshift generator, the same seed always gives the same numbers
+/// More info: https://en.wikipedia.org/wiki/Xorshift
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u32,
+}
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // Xorshift gets stuck on zero forever
+        Self { state: seed.max(1) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+    /// Returns a number from 0 to `n` (excluding `n`)
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+}
+/// Seed that is different every time, for when the user wants new numbers
+pub fn random_seed() -> u32 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.subsec_nanos() ^ now.as_secs() as u32
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
@@ -0,0 +1,54 @@
+//! Reading the games from files and zip archives
+
+mod zip;
+
+use std::{fmt::Display, fs, io, path::Path};
+
+use crate::cpu::MAX_ROM_SIZE;
+
+// Errors
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    TooLarge(usize),
+    InvalidZip,
+    NoGamesInZip,
+}
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::TooLarge(s) => write!(f, "Game is too large ({s} bytes, max is {MAX_ROM_SIZE})"),
+            Self::InvalidZip => write!(f, "Broken or unsupported zip archive"),
+            Self::NoGamesInZip => write!(f, "No .ch8 games in the zip archive"),
+        }
+    }
+}
+
+/// Read the game at `path`
+/// Zip archives are read in memory, the first `.ch8` game from it is returned
+pub fn read(path: &Path) -> Result<Vec<u8>, RomError> {
+    let bytes = fs::read(path).map_err(RomError::Io)?;
+
+    let is_zip = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        if bytes.len() > MAX_ROM_SIZE {
+            return Err(RomError::TooLarge(bytes.len()));
+        }
+        return Ok(bytes);
+    }
+
+    // Games that don't fit in the memory are just skipped
+    let entries = zip::read(&bytes, |name, size| {
+        name.to_ascii_lowercase().ends_with(".ch8") && size <= MAX_ROM_SIZE
+    }).ok_or(RomError::InvalidZip)?;
+
+    for entry in entries.iter().skip(1) {
+        crate::debug!("Skipped the game in the zip archive: {}", entry.name);
+    }
+
+    let entry = entries.into_iter().next().ok_or(RomError::NoGamesInZip)?;
+    crate::debug!("Loading the game from the zip archive: {}", entry.name);
+
+    Ok(entry.data)
+}
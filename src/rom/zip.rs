@@ -0,0 +1,272 @@
+//! Just enough of the zip format to read games from ROM packs
+//!
+//! More info:
+//! - https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+//! - https://www.rfc-editor.org/rfc/rfc1951 (deflate)
+
+/// Signature of the end of central directory record
+const END_SIGNATURE: u32 = 0x06054b50;
+/// Signature of the central directory file header
+const CENTRAL_SIGNATURE: u32 = 0x02014b50;
+/// Signature of the local file header
+const LOCAL_SIGNATURE: u32 = 0x04034b50;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+/// File in the archive
+pub struct Entry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Read the files of the archive that satisfy `filter(name, uncompressed_size)`
+/// Returns `None` if the archive is broken or uses unsupported compression for such files
+pub fn read(bytes: &[u8], filter: impl Fn(&str, usize) -> bool) -> Option<Vec<Entry>> {
+    // End record is at the very end of the archive, but it may be followed by a comment
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(bytes, at) == Some(END_SIGNATURE))?;
+
+    let count = u16_at(bytes, end + 10)? as usize;
+    let mut at = u32_at(bytes, end + 16)? as usize;
+    let mut entries = vec![];
+
+    for _ in 0..count {
+        if u32_at(bytes, at)? != CENTRAL_SIGNATURE { return None }
+
+        let method = u16_at(bytes, at + 10)?;
+        let compressed_size = u32_at(bytes, at + 20)? as usize;
+        let size = u32_at(bytes, at + 24)? as usize;
+        let name_len = u16_at(bytes, at + 28)? as usize;
+        let extra_len = u16_at(bytes, at + 30)? as usize;
+        let comment_len = u16_at(bytes, at + 32)? as usize;
+        let local = u32_at(bytes, at + 42)? as usize;
+        let name = String::from_utf8_lossy(bytes.get(at + 46..at + 46 + name_len)?).into_owned();
+
+        at += 46 + name_len + extra_len + comment_len;
+
+        // Skip directories and unwanted files before decompressing anything
+        if name.ends_with('/') || !filter(&name, size) { continue }
+
+        // Local header may have a different extra field
+        if u32_at(bytes, local)? != LOCAL_SIGNATURE { return None }
+        let start = local + 30 + u16_at(bytes, local + 26)? as usize + u16_at(bytes, local + 28)? as usize;
+        let compressed = bytes.get(start..start + compressed_size)?;
+
+        let data = match method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATE => inflate(compressed, size)?,
+            _ => return None
+        };
+        if data.len() != size { return None }
+
+        entries.push(Entry { name, data });
+    }
+
+    Some(entries)
+}
+
+// Deflate
+/// Base lengths for the length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances for the distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order of the code length code lengths in the dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Reads bits starting from the least significant one
+struct Bits<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+impl<'a> Bits<'a> {
+    fn read(&mut self, n: u8) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self.bytes.get(self.pos)?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Some(value)
+    }
+    /// Skip the rest of the current byte
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols sorted by their codes
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Option<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for len in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code < first + count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+}
+
+/// Decompress deflate data, that is expected to be `size` bytes long
+fn inflate(bytes: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut bits = Bits { bytes, pos: 0, bit: 0 };
+    let mut out = Vec::with_capacity(size);
+
+    loop {
+        let last = bits.read(1)? == 1;
+
+        match bits.read(2)? {
+            // Stored block
+            0 => {
+                bits.align();
+                let len = u16_at(bytes, bits.pos)? as usize;
+                bits.pos += 4;
+
+                out.extend_from_slice(bytes.get(bits.pos..bits.pos + len)?);
+                bits.pos += len;
+            }
+            // Fixed Huffman codes
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+
+                inflate_block(&mut bits, &mut out, size, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            // Dynamic Huffman codes
+            2 => {
+                let lit_count = bits.read(5)? as usize + 257;
+                let dist_count = bits.read(5)? as usize + 1;
+                let code_count = bits.read(4)? as usize + 4;
+
+                let mut code_lengths = [0u8; 19];
+                for &i in &CODE_LENGTH_ORDER[..code_count] {
+                    code_lengths[i] = bits.read(3)? as u8;
+                }
+                let code = Huffman::new(&code_lengths);
+
+                let mut lengths = vec![];
+                while lengths.len() < lit_count + dist_count {
+                    let (len, repeat) = match code.decode(&mut bits)? {
+                        len @ 0..=15 => (len as u8, 1),
+                        16 => (*lengths.last()?, 3 + bits.read(2)?),
+                        17 => (0, 3 + bits.read(3)?),
+                        18 => (0, 11 + bits.read(7)?),
+                        _ => return None
+                    };
+                    lengths.extend(std::iter::repeat_n(len, repeat as usize));
+                }
+                if lengths.len() != lit_count + dist_count { return None }
+
+                let lit = Huffman::new(&lengths[..lit_count]);
+                let dist = Huffman::new(&lengths[lit_count..]);
+                inflate_block(&mut bits, &mut out, size, &lit, &dist)?;
+            }
+            _ => return None
+        }
+
+        // Don't let a broken archive eat all the memory
+        if out.len() > size { return None }
+        if last { break }
+    }
+
+    Some(out)
+}
+
+fn inflate_block(bits: &mut Bits, out: &mut Vec<u8>, size: usize, lit: &Huffman, dist: &Huffman) -> Option<()> {
+    loop {
+        match lit.decode(bits)? {
+            byte @ 0..=255 => out.push(byte as u8),
+            // End of the block
+            256 => return Some(()),
+            symbol => {
+                let i = symbol as usize - 257;
+                let len = *LENGTH_BASE.get(i)? as usize + bits.read(*LENGTH_EXTRA.get(i)?)? as usize;
+
+                let i = dist.decode(bits)? as usize;
+                let distance = *DIST_BASE.get(i)? as usize + bits.read(*DIST_EXTRA.get(i)?)? as usize;
+                if distance > out.len() { return None }
+
+                // Copy byte by byte, because the copied bytes may overlap
+                for _ in 0..len {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+
+        if out.len() > size { return None }
+    }
+}
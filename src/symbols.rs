@@ -0,0 +1,70 @@
+//! Labels of the game addresses, for the debug output (see `--symbols`)
+//!
+//! Every line of the symbol file is an address and its label, like "0x2A0 main_loop",
+//! the address may be written without the "0x" and the lines starting with `#` are ignored
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+// Errors
+#[derive(Debug)]
+pub enum SymbolsError {
+    Io(PathBuf, io::Error),
+    InvalidLine(PathBuf, usize),
+}
+impl Display for SymbolsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(p, e) => write!(f, "Unable to read symbols {}: {e}", p.display()),
+            Self::InvalidLine(p, l) => write!(f, "{}:{l}: Expected \"<ADDR> <LABEL>\"", p.display()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+}
+impl Symbols {
+    pub fn load(path: &Path) -> Result<Self, SymbolsError> {
+        let text = fs::read_to_string(path).map_err(|e| SymbolsError::Io(path.into(), e))?;
+        let mut labels = BTreeMap::new();
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let invalid = || SymbolsError::InvalidLine(path.into(), line_idx + 1);
+            let mut words = line.split_whitespace();
+            let (Some(addr), Some(label), None) = (words.next(), words.next(), words.next()) else {
+                return Err(invalid());
+            };
+
+            let addr = addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")).unwrap_or(addr);
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| invalid())?;
+            labels.insert(addr, label.to_string());
+        }
+
+        Ok(Self { labels })
+    }
+
+    /// Label of the `addr`, or of the closest address before it with the offset, like "main_loop+4"
+    pub fn label(&self, addr: u16) -> Option<String> {
+        let (&start, label) = self.labels.range(..=addr).next_back()?;
+
+        if start == addr { Some(label.clone()) }
+        else { Some(format!("{}+{}", label, addr - start)) }
+    }
+    /// Address with its label, if it has one, like "0x2A0 <main_loop>"
+    pub fn annotate(&self, addr: u16) -> String {
+        match self.label(addr) {
+            Some(label) => format!("{:#05X} <{}>", addr, label),
+            None => format!("{:#05X}", addr),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Approximate durations of the instructions on the COSMAC VIP, used when the timing should
+//! feel like the original hardware (see [crate::config::Config::accurate_timing])
+//!
+//! Durations are in machine cycles of the CDP1802 CPU (~1.76 MHz / 8):
+//! - Simple register instructions are the cheapest
+//! - Arithmetic, jumps and skips cost a bit more, because the interpreter decodes them further
+//! - Memory instructions cost more for each register or digit they store
+//! - Drawing a sprite is by far the most expensive, the VIP interpreter also waits for the
+//!   display interrupt, so only a couple of sprites can be drawn in a frame
+//!
+//! These are rough estimates! Real durations also depend on the operands
+//! (for example, on the sprite position), which is ignored here
+
+/// Machine cycles executed by the COSMAC VIP in one 60 Hz frame
+pub const CYCLES_PER_FRAME: u32 = 3668;
+
+/// Returns how many machine cycles the instruction takes
+pub fn cost(ins: u16) -> u32 {
+    let x = ((ins & 0x0F00) >> 8) as u32;
+    let y = ((ins & 0x00F0) >> 4) as u32;
+    let n = (ins & 0x000F) as u32;
+
+    match (ins & 0xF000, ins & 0x00FF) {
+        // Clear the display
+        (0x0000, 0xE0) => 24,
+        // Return from a subroutine
+        (0x0000, 0xEE) => 10,
+        // Machine code calls and unknown instructions
+        (0x0000, _) => 10,
+        // Jump, call
+        (0x1000 | 0x2000, _) => 12,
+        (0xB000, _) => 22,
+        // Store/read Vx through Vy (XO-CHIP), estimated like Fx55 and Fx65
+        (0x5000, _) if n == 2 || n == 3 => 14 + 14 * (x.abs_diff(y) + 1),
+        // Skips
+        (0x3000 | 0x4000 | 0x5000 | 0x9000, _) => 14,
+        // Vx = KK, Vx += KK
+        (0x6000, _) => 6,
+        (0x7000, _) => 10,
+        // Arithmetic and logic
+        (0x8000, _) => 44,
+        // I = NNN
+        (0xA000, _) => 12,
+        // Random number
+        (0xC000, _) => 36,
+        // Draw a N-byte sprite, including the wait for the display interrupt
+        (0xD000, _) => 1600 + 68 * n,
+        // Key skips
+        (0xE000, _) => 14,
+        // Timers
+        (0xF000, 0x07 | 0x15 | 0x18) => 10,
+        // I += Vx, point I to a font sprite
+        (0xF000, 0x1E) => 18,
+        (0xF000, 0x29) => 20,
+        // Store BCD, it is calculated by repeated subtraction
+        (0xF000, 0x33) => 84 + 16 * 9,
+        // Store/read V0 through Vx
+        (0xF000, 0x55 | 0x65) => 14 + 14 * (x + 1),
+        _ => 10
+    }
+}